@@ -0,0 +1,96 @@
+use crate::errors::Result;
+use rspotify::model::{
+    FullPlaylist, FullTrack, Page, PlaylistId, PlaylistItem, PrivateUser, SavedTrack,
+    SimplifiedPlaylist, TimeRange, UserId,
+};
+use rspotify::prelude::PlayableId;
+use rspotify::Token;
+use std::future::Future;
+
+/// The Spotify Web API calls used by the controller.
+///
+/// [`Client`](crate::Client) talks to Spotify, while
+/// [`FakeSpotify`](crate::fake::FakeSpotify) keeps everything in memory so
+/// the automations can be tested offline.
+pub trait SpotifyApi: Send + Sync {
+    /// Returns the number of requests sent to the API.
+    fn calls(&self) -> usize;
+
+    fn token(&self) -> impl Future<Output = Result<Option<Token>>> + Send;
+
+    fn current_user(&self) -> impl Future<Output = Result<PrivateUser>> + Send;
+
+    fn playlist(&self, id: PlaylistId<'_>) -> impl Future<Output = Result<FullPlaylist>> + Send;
+
+    fn playlist_items(
+        &self,
+        id: PlaylistId<'_>,
+        offset: u32,
+    ) -> impl Future<Output = Result<Page<PlaylistItem>>> + Send;
+
+    fn current_user_playlists(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> impl Future<Output = Result<Page<SimplifiedPlaylist>>> + Send;
+
+    fn current_user_top_tracks(
+        &self,
+        time_range: Option<TimeRange>,
+        limit: u32,
+        offset: u32,
+    ) -> impl Future<Output = Result<Page<FullTrack>>> + Send;
+
+    fn current_user_saved_tracks(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> impl Future<Output = Result<Page<SavedTrack>>> + Send;
+
+    fn create_playlist(
+        &self,
+        user_id: UserId<'_>,
+        name: &str,
+        description: Option<&str>,
+    ) -> impl Future<Output = Result<FullPlaylist>> + Send;
+
+    fn playlist_check_follow(
+        &self,
+        id: PlaylistId<'_>,
+        user_id: UserId<'_>,
+    ) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Adds the items at `position` or at the end of the playlist and returns
+    /// the new snapshot ID.
+    fn playlist_add_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[PlayableId<'static>],
+        position: Option<u32>,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    fn playlist_replace_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[PlayableId<'static>],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Moves the item at `from` in front of the item at `to` and returns the
+    /// new snapshot ID.
+    fn playlist_reorder_item(
+        &self,
+        id: PlaylistId<'_>,
+        from: usize,
+        to: usize,
+        snapshot_id: &str,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Removes the given items at the given positions from the playlist and
+    /// returns the new snapshot ID.
+    fn playlist_remove_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[(usize, PlayableId<'static>)],
+        snapshot_id: &str,
+    ) -> impl Future<Output = Result<String>> + Send;
+}
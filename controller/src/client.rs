@@ -0,0 +1,284 @@
+use crate::api::SpotifyApi;
+use crate::errors::{Error, Result};
+use crate::ratelimit::RateLimiter;
+use crate::retry::{classify, status_code, Backoff, ErrorClass, Idempotency};
+use rspotify::http::HttpError;
+use rspotify::model::{
+    FullPlaylist, FullTrack, ItemPositions, Page, PlaylistId, PlaylistItem, PrivateUser,
+    SavedTrack, SimplifiedPlaylist, TimeRange, UserId,
+};
+use rspotify::prelude::{BaseClient, OAuthClient, PlayableId};
+use rspotify::{AuthCodeSpotify, ClientError, ClientResult, Token};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_RATE_LIMIT_RETRIES: usize = 5;
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Wraps the Spotify API client so that every request passes the shared rate
+/// limiter, rate limited requests are retried after the duration requested
+/// by Spotify and idempotent requests are retried on transient errors.
+pub struct Client {
+    spotify: AuthCodeSpotify,
+    limiter: Arc<RateLimiter>,
+    backoff: Backoff,
+    calls: AtomicUsize,
+}
+
+impl Client {
+    pub fn new(spotify: AuthCodeSpotify, limiter: Arc<RateLimiter>, backoff: Backoff) -> Self {
+        Client {
+            spotify,
+            limiter,
+            backoff,
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    async fn call<T, F, Fut>(&self, idempotency: Idempotency, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = ClientResult<T>> + Send,
+    {
+        let mut rate_limit_retries = 0;
+        let mut retries = 0;
+
+        loop {
+            self.limiter.acquire().await?;
+            self.calls.fetch_add(1, Ordering::Relaxed);
+
+            let err = match f().await {
+                Ok(v) => return Ok(v),
+                Err(err) => err,
+            };
+
+            match classify(&err) {
+                ErrorClass::RateLimited(wait)
+                    if rate_limit_retries < MAX_RATE_LIMIT_RETRIES && wait <= MAX_RETRY_AFTER =>
+                {
+                    log::warn!("Spotify rate limit exceeded, retrying after {wait:?}");
+                    self.limiter.block_for(wait)?;
+                    rate_limit_retries += 1;
+                }
+                ErrorClass::RateLimited(wait) => return Err(Error::RateLimited(wait)),
+                ErrorClass::Transient
+                    if idempotency == Idempotency::Idempotent
+                        && retries < self.backoff.max_retries =>
+                {
+                    let delay = self.backoff.delay(retries);
+                    retries += 1;
+                    log::warn!(
+                        "Spotify request failed, retrying after {delay:?} ({retries}/{}): {err}",
+                        self.backoff.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                _ => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl SpotifyApi for Client {
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    async fn token(&self) -> Result<Option<Token>> {
+        let token = self.spotify.get_token();
+        let token = token.lock().await.map_err(|_| Error::LockPoisoned)?;
+        Ok(token.clone())
+    }
+
+    async fn current_user(&self) -> Result<PrivateUser> {
+        self.call(Idempotency::Idempotent, || self.spotify.current_user())
+            .await
+    }
+
+    async fn playlist(&self, id: PlaylistId<'_>) -> Result<FullPlaylist> {
+        self.call(Idempotency::Idempotent, || {
+            self.spotify.playlist(id.clone(), None, None)
+        })
+        .await
+    }
+
+    async fn playlist_items(&self, id: PlaylistId<'_>, offset: u32) -> Result<Page<PlaylistItem>> {
+        self.call(Idempotency::Idempotent, || {
+            self.spotify
+                .playlist_items_manual(id.clone(), None, None, None, Some(offset))
+        })
+        .await
+    }
+
+    async fn current_user_playlists(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Page<SimplifiedPlaylist>> {
+        self.call(Idempotency::Idempotent, || {
+            self.spotify
+                .current_user_playlists_manual(Some(limit), Some(offset))
+        })
+        .await
+    }
+
+    async fn current_user_top_tracks(
+        &self,
+        time_range: Option<TimeRange>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Page<FullTrack>> {
+        self.call(Idempotency::Idempotent, || {
+            self.spotify
+                .current_user_top_tracks_manual(time_range, Some(limit), Some(offset))
+        })
+        .await
+    }
+
+    async fn current_user_saved_tracks(&self, limit: u32, offset: u32) -> Result<Page<SavedTrack>> {
+        self.call(Idempotency::Idempotent, || {
+            self.spotify
+                .current_user_saved_tracks_manual(None, Some(limit), Some(offset))
+        })
+        .await
+    }
+
+    async fn create_playlist(
+        &self,
+        user_id: UserId<'_>,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<FullPlaylist> {
+        self.call(Idempotency::NonIdempotent, || {
+            self.spotify.user_playlist_create(
+                user_id.clone(),
+                name,
+                Some(false),
+                Some(false),
+                description,
+            )
+        })
+        .await
+    }
+
+    async fn playlist_check_follow(&self, id: PlaylistId<'_>, user_id: UserId<'_>) -> Result<bool> {
+        let follows = self
+            .call(Idempotency::Idempotent, || {
+                self.spotify
+                    .playlist_check_follow(id.clone(), std::slice::from_ref(&user_id))
+            })
+            .await?;
+        Ok(follows.first().copied().unwrap_or_default())
+    }
+
+    async fn playlist_add_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[PlayableId<'static>],
+        position: Option<u32>,
+    ) -> Result<String> {
+        let res = self
+            .call(Idempotency::NonIdempotent, || {
+                self.spotify
+                    .playlist_add_items(id.clone(), items.iter().cloned(), position)
+            })
+            .await?;
+        Ok(res.snapshot_id)
+    }
+
+    async fn playlist_replace_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[PlayableId<'static>],
+    ) -> Result<()> {
+        self.call(Idempotency::Idempotent, || {
+            self.spotify
+                .playlist_replace_items(id.clone(), items.iter().cloned())
+        })
+        .await
+    }
+
+    async fn playlist_reorder_item(
+        &self,
+        id: PlaylistId<'_>,
+        from: usize,
+        to: usize,
+        snapshot_id: &str,
+    ) -> Result<String> {
+        // Positions refer to the given snapshot, so sending the request again
+        // doesn't move another item.
+        let res = self
+            .call(Idempotency::Idempotent, || {
+                self.spotify.playlist_reorder_items(
+                    id.clone(),
+                    Some(from as i32),
+                    Some(to as i32),
+                    None,
+                    Some(snapshot_id),
+                )
+            })
+            .await?;
+        Ok(res.snapshot_id)
+    }
+
+    async fn playlist_remove_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[(usize, PlayableId<'static>)],
+        snapshot_id: &str,
+    ) -> Result<String> {
+        let positions: Vec<_> = items.iter().map(|(pos, _)| [*pos as u32]).collect();
+
+        // Positions refer to the given snapshot, so sending the request again
+        // doesn't remove another occurrence.
+        let res = self
+            .call(Idempotency::Idempotent, || {
+                let items =
+                    items
+                        .iter()
+                        .zip(&positions)
+                        .map(|((_, item), positions)| ItemPositions {
+                            id: item.clone(),
+                            positions,
+                        });
+                self.spotify.playlist_remove_specific_occurrences_of_items(
+                    id.clone(),
+                    items,
+                    Some(snapshot_id),
+                )
+            })
+            .await?;
+        Ok(res.snapshot_id)
+    }
+}
+
+pub fn is_not_found(err: &Error) -> bool {
+    matches!(err, Error::SpotifyClient(err) if status_code(err) == Some(404))
+}
+
+/// Converts an error of a token refresh. Spotify responds with `invalid_grant`
+/// when the refresh token has been revoked, e.g. because the user removed
+/// the app from their account.
+pub async fn token_error(err: ClientError) -> Error {
+    let ClientError::Http(http_err) = err else {
+        return err.into();
+    };
+    let HttpError::StatusCode(res) = *http_err else {
+        return ClientError::Http(http_err).into();
+    };
+
+    let status = res.status();
+    let body = res.text().await.unwrap_or_default();
+    let error = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string));
+
+    match error.as_deref() {
+        Some("invalid_grant") => Error::TokenRevoked,
+        _ => {
+            Error::AuthorizationFailed(format!("token refresh failed with {status}: {body}").into())
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::errors::{Error, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use std::fmt;
+
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 24;
+
+/// Encrypts values stored in the KV store with XChaCha20-Poly1305.
+///
+/// Values are always encrypted with the current key. Old keys are only used
+/// for decryption, so the key can be rotated by moving the current key to the
+/// old keys and re-encrypting the stored values.
+#[derive(Clone)]
+pub struct Cipher {
+    current: XChaCha20Poly1305,
+    old: Vec<XChaCha20Poly1305>,
+}
+
+/// A decrypted value.
+pub(crate) struct Decrypted {
+    pub plaintext: String,
+    /// Whether the value has not been encrypted with the current key and
+    /// should be re-encrypted.
+    pub stale: bool,
+}
+
+impl Cipher {
+    /// Creates a cipher from base64 encoded 256 bit keys.
+    pub fn new(current: &str, old: &[&str]) -> Result<Self> {
+        Ok(Cipher {
+            current: parse_key(current)?,
+            old: old
+                .iter()
+                .map(|key| parse_key(key))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Encrypts the value. `context` is authenticated along with it, so the
+    /// result can't be decrypted in another context.
+    pub(crate) fn encrypt(&self, plaintext: &str, context: &str) -> String {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: context.as_bytes(),
+        };
+        let ciphertext = self
+            .current
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .expect("encrypting in memory can not fail");
+
+        format!(
+            "{PREFIX}{}",
+            BASE64.encode([&nonce[..], &ciphertext].concat())
+        )
+    }
+
+    /// Decrypts a value returned by [`Cipher::encrypt`]. Values which are
+    /// not encrypted are returned as they are, so they are encrypted when
+    /// they are written again.
+    pub(crate) fn decrypt(&self, value: &str, context: &str) -> Result<Decrypted> {
+        let Some(encoded) = value.strip_prefix(PREFIX) else {
+            return Ok(Decrypted {
+                plaintext: value.to_string(),
+                stale: true,
+            });
+        };
+
+        let data = BASE64
+            .decode(encoded)
+            .map_err(|_| Error::TokenDecryptionFailed)?;
+        if data.len() < NONCE_LEN {
+            return Err(Error::TokenDecryptionFailed);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+        let keys = std::iter::once(&self.current).chain(&self.old);
+        for (i, key) in keys.enumerate() {
+            let payload = Payload {
+                msg: ciphertext,
+                aad: context.as_bytes(),
+            };
+            if let Ok(plaintext) = key.decrypt(XNonce::from_slice(nonce), payload) {
+                return Ok(Decrypted {
+                    plaintext: String::from_utf8(plaintext)
+                        .map_err(|_| Error::TokenDecryptionFailed)?,
+                    stale: i > 0,
+                });
+            }
+        }
+
+        Err(Error::TokenDecryptionFailed)
+    }
+}
+
+/// Returns whether the value has been encrypted by a [`Cipher`].
+pub(crate) fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+fn parse_key(key: &str) -> Result<XChaCha20Poly1305> {
+    let key = BASE64
+        .decode(key.trim())
+        .map_err(|err| Error::InvalidEncryptionKey(err.to_string()))?;
+    XChaCha20Poly1305::new_from_slice(&key)
+        .map_err(|_| Error::InvalidEncryptionKey(format!("expected 32 bytes, got {}", key.len())))
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the keys.
+        f.debug_struct("Cipher")
+            .field("old_keys", &self.old.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const KEY_B: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBA=";
+
+    #[test]
+    fn test_cipher() {
+        let cipher = Cipher::new(KEY_A, &[]).unwrap();
+        let encrypted = cipher.encrypt("secret", "token");
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret"));
+        assert_ne!(encrypted, cipher.encrypt("secret", "token"));
+
+        let decrypted = cipher.decrypt(&encrypted, "token").unwrap();
+        assert_eq!(decrypted.plaintext, "secret");
+        assert!(!decrypted.stale);
+
+        assert!(matches!(
+            cipher.decrypt(&encrypted, "other"),
+            Err(Error::TokenDecryptionFailed)
+        ));
+        let mut tampered = encrypted.clone().into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(cipher
+            .decrypt(std::str::from_utf8(&tampered).unwrap(), "token")
+            .is_err());
+
+        let plain = cipher.decrypt("secret", "token").unwrap();
+        assert_eq!(plain.plaintext, "secret");
+        assert!(plain.stale);
+
+        let rotated = Cipher::new(KEY_B, &[KEY_A]).unwrap();
+        let decrypted = rotated.decrypt(&encrypted, "token").unwrap();
+        assert_eq!(decrypted.plaintext, "secret");
+        assert!(decrypted.stale);
+        assert!(matches!(
+            Cipher::new(KEY_B, &[])
+                .unwrap()
+                .decrypt(&encrypted, "token"),
+            Err(Error::TokenDecryptionFailed)
+        ));
+
+        assert!(matches!(
+            Cipher::new("c2hvcnQ=", &[]),
+            Err(Error::InvalidEncryptionKey(_))
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// A single change which needs to be applied to a playlist to transform its
+/// current items into the desired items.
+///
+/// Positions always refer to the state of the playlist after all preceding
+/// changes have been applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<T> {
+    /// Remove the items at the given positions. The positions are sorted in
+    /// descending order, so removing them in chunks keeps the remaining
+    /// positions valid.
+    Remove(Vec<(usize, T)>),
+
+    /// Move the item at `from` in front of the item currently at `to`.
+    Move { from: usize, to: usize },
+
+    /// Insert the given items at `position`.
+    Insert { position: usize, items: Vec<T> },
+}
+
+/// Computes the changes required to turn `current` into `desired`.
+///
+/// Entries in `current` which are `None` (items which can not be identified,
+/// like local files) are never touched. Items which are in the right relative
+/// order are kept in place, so only the minimal amount of items is moved.
+pub fn diff<T: Clone + Eq + Hash>(current: &[Option<T>], desired: &[T]) -> Vec<Change<T>> {
+    let mut changes = vec![];
+
+    let mut desired_positions: HashMap<&T, VecDeque<usize>> = HashMap::new();
+    for (i, item) in desired.iter().enumerate() {
+        desired_positions.entry(item).or_default().push_back(i);
+    }
+
+    // Map each current item to its target index in the desired list. Items
+    // which are not desired (anymore) are removed.
+    let mut removals = vec![];
+    let mut working: Vec<Option<usize>> = Vec::with_capacity(current.len());
+    for (i, item) in current.iter().enumerate() {
+        let Some(item) = item else {
+            working.push(None);
+            continue;
+        };
+
+        match desired_positions
+            .get_mut(item)
+            .and_then(VecDeque::pop_front)
+        {
+            Some(target) => working.push(Some(target)),
+            None => removals.push((i, item.clone())),
+        }
+    }
+
+    if !removals.is_empty() {
+        removals.reverse();
+        changes.push(Change::Remove(removals));
+    }
+
+    let mut present = vec![false; desired.len()];
+    let mut placed = vec![false; desired.len()];
+
+    let targets: Vec<_> = working.iter().flatten().copied().collect();
+    for &target in &targets {
+        present[target] = true;
+    }
+    for target in longest_increasing_subsequence(&targets) {
+        placed[target] = true;
+    }
+
+    let mut x = 0;
+    while x < desired.len() {
+        if placed[x] {
+            x += 1;
+            continue;
+        }
+
+        // All items before x are placed at this point, so x is put directly
+        // behind its predecessor.
+        let position = match x {
+            0 => 0,
+            _ => position_of(&working, x - 1) + 1,
+        };
+
+        if present[x] {
+            let from = position_of(&working, x);
+            if from != position {
+                changes.push(Change::Move { from, to: position });
+                working.remove(from);
+                working.insert(
+                    if position > from { position - 1 } else { position },
+                    Some(x),
+                );
+            }
+            placed[x] = true;
+            x += 1;
+            continue;
+        }
+
+        let end = (x..desired.len())
+            .find(|&i| present[i])
+            .unwrap_or(desired.len());
+        changes.push(Change::Insert {
+            position,
+            items: desired[x..end].to_vec(),
+        });
+        working.splice(position..position, (x..end).map(Some));
+        placed[x..end].fill(true);
+        x = end;
+    }
+
+    changes
+}
+
+fn position_of(working: &[Option<usize>], target: usize) -> usize {
+    working
+        .iter()
+        .position(|v| *v == Some(target))
+        .expect("placed target must be in working list")
+}
+
+fn longest_increasing_subsequence(values: &[usize]) -> Vec<usize> {
+    // tails[k] holds the index into values of the smallest tail of all
+    // increasing subsequences with length k + 1.
+    let mut tails: Vec<usize> = vec![];
+    let mut prev: Vec<Option<usize>> = vec![None; values.len()];
+
+    for (i, &value) in values.iter().enumerate() {
+        let k = tails.partition_point(|&t| values[t] < value);
+        if k > 0 {
+            prev[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut res = vec![];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        res.push(values[i]);
+        next = prev[i];
+    }
+    res.reverse();
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(current: &[Option<char>], changes: Vec<Change<char>>) -> Vec<Option<char>> {
+        let mut items = current.to_vec();
+        for change in changes {
+            match change {
+                Change::Remove(removals) => {
+                    for (pos, item) in removals {
+                        assert_eq!(items.remove(pos), Some(item));
+                    }
+                }
+                Change::Move { from, to } => {
+                    let item = items.remove(from);
+                    items.insert(if to > from { to - 1 } else { to }, item);
+                }
+                Change::Insert {
+                    position,
+                    items: new,
+                } => {
+                    items.splice(position..position, new.into_iter().map(Some));
+                }
+            }
+        }
+        items
+    }
+
+    fn check(current: &str, desired: &str) -> Vec<Change<char>> {
+        let current: Vec<_> = current.chars().map(|c| (c != '_').then_some(c)).collect();
+        let desired: Vec<_> = desired.chars().collect();
+        let changes = diff(&current, &desired);
+
+        let res: String = apply(&current, changes.clone())
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(res, desired.iter().collect::<String>());
+
+        changes
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(check("abc", "abc"), vec![]);
+        assert_eq!(check("", ""), vec![]);
+        assert_eq!(
+            check("", "ab"),
+            vec![Change::Insert {
+                position: 0,
+                items: vec!['a', 'b']
+            }]
+        );
+        assert_eq!(
+            check("abc", ""),
+            vec![Change::Remove(vec![(2, 'c'), (1, 'b'), (0, 'a')])]
+        );
+        assert_eq!(check("dabc", "abcd"), vec![Change::Move { from: 0, to: 4 }]);
+        assert_eq!(check("abcd", "dabc"), vec![Change::Move { from: 3, to: 0 }]);
+        assert_eq!(
+            check("axbyc", "abzc"),
+            vec![
+                Change::Remove(vec![(3, 'y'), (1, 'x')]),
+                Change::Insert {
+                    position: 2,
+                    items: vec!['z']
+                }
+            ]
+        );
+
+        check("aab", "aba");
+        check("abcabc", "cba");
+        check("a_b_c", "cxba");
+        check("fedcba", "abcdefg");
+    }
+
+    #[test]
+    fn test_longest_increasing_subsequence() {
+        assert_eq!(longest_increasing_subsequence(&[]), Vec::<usize>::new());
+        assert_eq!(longest_increasing_subsequence(&[3, 0, 1, 2]), vec![0, 1, 2]);
+        assert_eq!(
+            longest_increasing_subsequence(&[0, 4, 1, 2, 3]),
+            vec![0, 1, 2, 3]
+        );
+    }
+}
@@ -8,6 +8,9 @@ pub enum Error {
     #[error("spotify client error: {0}")]
     SpotifyClient(#[from] rspotify::ClientError),
 
+    #[error("spotify rate limit exceeded, retry after {0:?}")]
+    RateLimited(std::time::Duration),
+
     #[error("spotify authorization failed: {0}")]
     AuthorizationFailed(Box<dyn std::error::Error + Send + Sync>),
 
@@ -20,6 +23,9 @@ pub enum Error {
     #[error("env variable not found: {name}: {err}")]
     EnvVar { name: &'static str, err: VarError },
 
+    #[error("invalid value for env variable {name}: {value}")]
+    InvalidEnvVar { name: &'static str, value: String },
+
     #[error("spotify id error: {0}")]
     SpotifyId(#[from] rspotify::model::IdError),
 
@@ -32,12 +38,52 @@ pub enum Error {
     #[error("no authorization token has been stored before")]
     NoTokenStored,
 
+    #[error("the spotify authorization has been revoked, the app needs to be authorized again")]
+    TokenRevoked,
+
     #[error("no playlist found")]
     NoPlaylistFound,
 
-    #[error("playlist by id doesn ot exist")]
+    #[error("stored playlist does not exist or has been unfollowed")]
     PlaylistDoesNotExist,
 
+    #[error("updating playlist failed, previous contents have been restored: {0}")]
+    PlaylistUpdateRolledBack(Box<Error>),
+
+    #[error(
+        "updating playlist failed ({err}); previous contents have been restored except for \
+        local files, which can not be re-added: {}",
+        .lost.join(", ")
+    )]
+    PlaylistLocalFilesLost { err: Box<Error>, lost: Vec<String> },
+
+    #[error(
+        "updating playlist failed ({err}) and restoring previous contents failed \
+        ({rollback_err}); playlist is left partially updated"
+    )]
+    PlaylistPartiallyUpdated {
+        err: Box<Error>,
+        rollback_err: Box<Error>,
+    },
+
+    #[error("invalid encryption key: {0}")]
+    InvalidEncryptionKey(String),
+
+    #[error("stored token could not be decrypted with any of the configured keys")]
+    TokenDecryptionFailed,
+
+    #[error("stored token is encrypted, but no encryption key is configured")]
+    NoEncryptionKey,
+
+    #[error("invalid cached access token: {0}")]
+    InvalidAccessToken(serde_json::Error),
+
+    #[error("invalid playlist record: {0}")]
+    InvalidRecord(#[from] serde_json::Error),
+
+    #[error("playlist record version {0} is not supported, the app needs to be updated")]
+    UnsupportedRecordVersion(u32),
+
     #[error("invalid year: {0}")]
     InvalidYear(#[from] ParseIntError),
 }
@@ -0,0 +1,598 @@
+use crate::api::SpotifyApi;
+use crate::errors::{Error, Result};
+use rspotify::http::HttpError;
+use rspotify::model::{
+    FullPlaylist, FullTrack, Page, PlaylistId, PlaylistItem, PrivateUser, SavedTrack,
+    SimplifiedPlaylist, TimeRange, UserId,
+};
+use rspotify::prelude::{Id, PlayableId};
+use rspotify::{ClientError, Token};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+const PLAYLIST_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: u32 = 50;
+const MAX_ITEMS_PER_WRITE: usize = 100;
+
+/// An item of a playlist in the [`FakeSpotify`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FakeItem {
+    Track(String),
+    Episode(String),
+    /// A local file, identified by its name.
+    Local(String),
+}
+
+impl FakeItem {
+    fn json(&self, release_dates: &HashMap<String, String>) -> Value {
+        match self {
+            FakeItem::Track(id) => track_json(Some(id), release_dates.get(id)),
+            FakeItem::Episode(id) => episode_json(id),
+            FakeItem::Local(name) => {
+                let mut track = track_json(None, None);
+                track["name"] = name.as_str().into();
+                track
+            }
+        }
+    }
+}
+
+impl From<PlayableId<'_>> for FakeItem {
+    fn from(id: PlayableId<'_>) -> Self {
+        match id {
+            PlayableId::Track(id) => FakeItem::Track(id.id().to_string()),
+            PlayableId::Episode(id) => FakeItem::Episode(id.id().to_string()),
+        }
+    }
+}
+
+struct FakePlaylist {
+    id: String,
+    name: String,
+    owner: String,
+    followed: bool,
+    items: Vec<FakeItem>,
+    snapshot: usize,
+}
+
+#[derive(Default)]
+struct State {
+    user_id: String,
+    release_dates: HashMap<String, String>,
+    top_tracks: Vec<(TimeRange, Vec<String>)>,
+    saved_tracks: Vec<String>,
+    playlists: Vec<FakePlaylist>,
+    next_playlist_id: usize,
+    writes: usize,
+    failing_writes: HashSet<usize>,
+}
+
+/// An in-memory implementation of [`SpotifyApi`] for tests.
+///
+/// It behaves like the parts of the Spotify Web API used by the controller:
+/// results are paginated, writes are checked against the playlist owner and
+/// snapshot, and unknown playlists result in a 404. Clones share their state,
+/// so a test can hand one clone to the controller and inspect the other.
+#[derive(Clone, Default)]
+pub struct FakeSpotify {
+    state: Arc<Mutex<State>>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FakeSpotify {
+    pub fn new(user_id: &str) -> FakeSpotify {
+        let fake = FakeSpotify::default();
+        fake.state().user_id = user_id.to_string();
+        fake
+    }
+
+    pub fn user_id(&self) -> String {
+        self.state().user_id.clone()
+    }
+
+    /// Sets the release date of the album of the given track.
+    pub fn add_track(&self, id: &str, release_date: &str) {
+        self.state()
+            .release_dates
+            .insert(id.to_string(), release_date.to_string());
+    }
+
+    pub fn set_top_tracks(&self, time_range: TimeRange, ids: &[&str]) {
+        let mut state = self.state();
+        state.top_tracks.retain(|(range, _)| *range != time_range);
+        state.top_tracks.push((time_range, strings(ids)));
+    }
+
+    pub fn set_saved_tracks(&self, ids: &[&str]) {
+        self.state().saved_tracks = strings(ids);
+    }
+
+    /// Adds a playlist which is followed by the current user.
+    pub fn add_playlist(
+        &self,
+        name: &str,
+        owner: &str,
+        items: Vec<FakeItem>,
+    ) -> PlaylistId<'static> {
+        let mut state = self.state();
+        let index = state.push_playlist(name, owner, items);
+        PlaylistId::from_id(state.playlists[index].id.clone())
+            .expect("generated playlist IDs are valid")
+    }
+
+    /// Returns the current items of the given playlist.
+    pub fn items(&self, id: &PlaylistId<'_>) -> Vec<FakeItem> {
+        let state = self.state();
+        state
+            .playlists
+            .iter()
+            .find(|p| p.id == id.id())
+            .map(|p| p.items.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the IDs of all followed playlists with the given name.
+    pub fn playlist_ids(&self, name: &str) -> Vec<PlaylistId<'static>> {
+        self.state()
+            .playlists
+            .iter()
+            .filter(|p| p.followed && p.name == name)
+            .filter_map(|p| PlaylistId::from_id(p.id.clone()).ok())
+            .collect()
+    }
+
+    /// Unfollows the playlist, which is how playlists are deleted in Spotify.
+    pub fn unfollow_playlist(&self, id: &PlaylistId<'_>) {
+        let mut state = self.state();
+        if let Some(p) = state.playlists.iter_mut().find(|p| p.id == id.id()) {
+            p.followed = false;
+        }
+    }
+
+    /// Removes the playlist entirely, so requests for it result in a 404.
+    pub fn remove_playlist(&self, id: &PlaylistId<'_>) {
+        self.state().playlists.retain(|p| p.id != id.id());
+    }
+
+    /// Makes the `n`-th write request from now on, starting at `0`, fail with
+    /// an internal server error.
+    pub fn fail_write(&self, n: usize) {
+        let mut state = self.state();
+        let write = state.writes + n;
+        state.failing_writes.insert(write);
+    }
+
+    /// Returns the number of write requests which have been sent so far.
+    pub fn writes(&self) -> usize {
+        self.state().writes
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn call(&self) -> MutexGuard<'_, State> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.state()
+    }
+}
+
+impl State {
+    fn push_playlist(&mut self, name: &str, owner: &str, items: Vec<FakeItem>) -> usize {
+        self.playlists.push(FakePlaylist {
+            id: format!("playlist{}", self.next_playlist_id),
+            name: name.to_string(),
+            owner: owner.to_string(),
+            followed: true,
+            items,
+            snapshot: 0,
+        });
+        self.next_playlist_id += 1;
+        self.playlists.len() - 1
+    }
+
+    /// Counts a write request to the given playlist and returns the index of
+    /// the playlist if the write is allowed.
+    fn write(&mut self, id: &PlaylistId<'_>) -> Result<usize> {
+        self.count_write()?;
+
+        let index = self.playlist(id)?;
+        if self.playlists[index].owner != self.user_id {
+            return Err(status_error(403));
+        }
+        self.playlists[index].snapshot += 1;
+
+        Ok(index)
+    }
+
+    fn count_write(&mut self) -> Result<()> {
+        let write = self.writes;
+        self.writes += 1;
+        match self.failing_writes.remove(&write) {
+            true => Err(status_error(500)),
+            false => Ok(()),
+        }
+    }
+
+    fn playlist(&self, id: &PlaylistId<'_>) -> Result<usize> {
+        self.playlists
+            .iter()
+            .position(|p| p.id == id.id())
+            .ok_or_else(|| status_error(404))
+    }
+
+    fn check_snapshot(&self, index: usize, snapshot_id: &str) -> Result<()> {
+        // Spotify applies positions relative to the given snapshot. The
+        // controller always sends the latest one, so anything else is a bug.
+        match self.playlists[index].snapshot.to_string() == snapshot_id {
+            true => Ok(()),
+            false => Err(status_error(400)),
+        }
+    }
+
+    fn full_playlist(&self, index: usize) -> Result<FullPlaylist> {
+        let playlist = &self.playlists[index];
+        parse(json!({
+            "collaborative": false,
+            "description": null,
+            "external_urls": {},
+            "followers": { "total": 0 },
+            "href": "",
+            "id": playlist.id,
+            "images": [],
+            "name": playlist.name,
+            "owner": user_json(&playlist.owner),
+            "public": false,
+            "snapshot_id": playlist.snapshot.to_string(),
+            "tracks": self.items_page(index, 0),
+        }))
+    }
+
+    fn simplified_playlist(&self, index: usize) -> Value {
+        let playlist = &self.playlists[index];
+        json!({
+            "collaborative": false,
+            "external_urls": {},
+            "href": "",
+            "id": playlist.id,
+            "images": [],
+            "name": playlist.name,
+            "owner": user_json(&playlist.owner),
+            "public": false,
+            "snapshot_id": playlist.snapshot.to_string(),
+            "tracks": { "href": "", "total": playlist.items.len() },
+        })
+    }
+
+    fn items_page(&self, index: usize, offset: usize) -> Value {
+        let items: Vec<_> = self.playlists[index]
+            .items
+            .iter()
+            .map(|item| {
+                json!({
+                    "added_at": null,
+                    "added_by": null,
+                    "is_local": matches!(item, FakeItem::Local(_)),
+                    "track": item.json(&self.release_dates),
+                })
+            })
+            .collect();
+        page_json(items, PLAYLIST_PAGE_SIZE, offset)
+    }
+
+    fn track(&self, id: &str) -> Value {
+        track_json(Some(id), self.release_dates.get(id))
+    }
+}
+
+impl SpotifyApi for FakeSpotify {
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    async fn token(&self) -> Result<Option<Token>> {
+        Ok(Some(Token {
+            refresh_token: Some("fake".to_string()),
+            ..Default::default()
+        }))
+    }
+
+    async fn current_user(&self) -> Result<PrivateUser> {
+        let state = self.call();
+        parse(json!({
+            "country": null,
+            "display_name": state.user_id,
+            "email": null,
+            "external_urls": {},
+            "explicit_content": null,
+            "followers": null,
+            "href": "",
+            "id": state.user_id,
+            "images": null,
+            "product": null,
+        }))
+    }
+
+    async fn playlist(&self, id: PlaylistId<'_>) -> Result<FullPlaylist> {
+        let state = self.call();
+        let index = state.playlist(&id)?;
+        state.full_playlist(index)
+    }
+
+    async fn playlist_items(&self, id: PlaylistId<'_>, offset: u32) -> Result<Page<PlaylistItem>> {
+        let state = self.call();
+        let index = state.playlist(&id)?;
+        parse(state.items_page(index, offset as usize))
+    }
+
+    async fn current_user_playlists(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Page<SimplifiedPlaylist>> {
+        let state = self.call();
+        check_limit(limit)?;
+        let playlists = (0..state.playlists.len())
+            .filter(|&i| state.playlists[i].followed)
+            .map(|i| state.simplified_playlist(i))
+            .collect();
+        parse(page_json(playlists, limit as usize, offset as usize))
+    }
+
+    async fn current_user_top_tracks(
+        &self,
+        time_range: Option<TimeRange>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Page<FullTrack>> {
+        let state = self.call();
+        check_limit(limit)?;
+        let time_range = time_range.unwrap_or(TimeRange::MediumTerm);
+        let tracks = state
+            .top_tracks
+            .iter()
+            .find(|(range, _)| *range == time_range)
+            .map(|(_, ids)| ids.iter().map(|id| state.track(id)).collect())
+            .unwrap_or_default();
+        parse(page_json(tracks, limit as usize, offset as usize))
+    }
+
+    async fn current_user_saved_tracks(&self, limit: u32, offset: u32) -> Result<Page<SavedTrack>> {
+        let state = self.call();
+        check_limit(limit)?;
+        let tracks = state
+            .saved_tracks
+            .iter()
+            .map(|id| json!({ "added_at": "2020-01-01T00:00:00Z", "track": state.track(id) }))
+            .collect();
+        parse(page_json(tracks, limit as usize, offset as usize))
+    }
+
+    async fn create_playlist(
+        &self,
+        user_id: UserId<'_>,
+        name: &str,
+        _description: Option<&str>,
+    ) -> Result<FullPlaylist> {
+        let mut state = self.call();
+        state.count_write()?;
+        if user_id.id() != state.user_id {
+            return Err(status_error(403));
+        }
+
+        let owner = state.user_id.clone();
+        let index = state.push_playlist(name, &owner, vec![]);
+        state.full_playlist(index)
+    }
+
+    async fn playlist_check_follow(&self, id: PlaylistId<'_>, user_id: UserId<'_>) -> Result<bool> {
+        let state = self.call();
+        let index = state.playlist(&id)?;
+        Ok(state.playlists[index].followed && user_id.id() == state.user_id)
+    }
+
+    async fn playlist_add_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[PlayableId<'static>],
+        position: Option<u32>,
+    ) -> Result<String> {
+        let mut state = self.call();
+        let index = state.write(&id)?;
+        let playlist = &mut state.playlists[index];
+
+        let position = position.map_or(playlist.items.len(), |p| p as usize);
+        if items.len() > MAX_ITEMS_PER_WRITE || position > playlist.items.len() {
+            return Err(status_error(400));
+        }
+
+        let items = items.iter().cloned().map(FakeItem::from);
+        playlist.items.splice(position..position, items);
+        Ok(playlist.snapshot.to_string())
+    }
+
+    async fn playlist_replace_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[PlayableId<'static>],
+    ) -> Result<()> {
+        let mut state = self.call();
+        let index = state.write(&id)?;
+        if items.len() > MAX_ITEMS_PER_WRITE {
+            return Err(status_error(400));
+        }
+
+        state.playlists[index].items = items.iter().cloned().map(FakeItem::from).collect();
+        Ok(())
+    }
+
+    async fn playlist_reorder_item(
+        &self,
+        id: PlaylistId<'_>,
+        from: usize,
+        to: usize,
+        snapshot_id: &str,
+    ) -> Result<String> {
+        let mut state = self.call();
+        let index = state.playlist(&id)?;
+        state.check_snapshot(index, snapshot_id)?;
+        let index = state.write(&id)?;
+        let playlist = &mut state.playlists[index];
+
+        if from >= playlist.items.len() || to > playlist.items.len() {
+            return Err(status_error(400));
+        }
+
+        let item = playlist.items.remove(from);
+        playlist
+            .items
+            .insert(if to > from { to - 1 } else { to }, item);
+        Ok(playlist.snapshot.to_string())
+    }
+
+    async fn playlist_remove_items(
+        &self,
+        id: PlaylistId<'_>,
+        items: &[(usize, PlayableId<'static>)],
+        snapshot_id: &str,
+    ) -> Result<String> {
+        let mut state = self.call();
+        let index = state.playlist(&id)?;
+        state.check_snapshot(index, snapshot_id)?;
+        let index = state.write(&id)?;
+        let playlist = &mut state.playlists[index];
+
+        if items.len() > MAX_ITEMS_PER_WRITE {
+            return Err(status_error(400));
+        }
+        for (pos, item) in items {
+            if playlist.items.get(*pos) != Some(&FakeItem::from(item.clone())) {
+                return Err(status_error(400));
+            }
+        }
+
+        let mut positions: Vec<_> = items.iter().map(|(pos, _)| *pos).collect();
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+        positions.dedup();
+        for pos in positions {
+            playlist.items.remove(pos);
+        }
+        Ok(playlist.snapshot.to_string())
+    }
+}
+
+fn strings(v: &[&str]) -> Vec<String> {
+    v.iter().map(|s| s.to_string()).collect()
+}
+
+fn check_limit(limit: u32) -> Result<()> {
+    match limit {
+        1..=MAX_PAGE_SIZE => Ok(()),
+        _ => Err(status_error(400)),
+    }
+}
+
+fn status_error(status: u16) -> Error {
+    let res = http::Response::builder()
+        .status(status)
+        .body("")
+        .expect("status codes used by the fake are valid");
+    ClientError::Http(Box::new(HttpError::StatusCode(res.into()))).into()
+}
+
+fn parse<T: DeserializeOwned>(v: Value) -> Result<T> {
+    Ok(serde_json::from_value(v).map_err(ClientError::from)?)
+}
+
+fn page_json(items: Vec<Value>, limit: usize, offset: usize) -> Value {
+    let total = items.len();
+    let items: Vec<_> = items.into_iter().skip(offset).take(limit).collect();
+    let next = (offset + items.len() < total).then_some("next");
+    json!({
+        "href": "",
+        "items": items,
+        "limit": limit,
+        "next": next,
+        "offset": offset,
+        "previous": null,
+        "total": total,
+    })
+}
+
+fn user_json(id: &str) -> Value {
+    json!({
+        "display_name": id,
+        "external_urls": {},
+        "followers": null,
+        "href": "",
+        "id": id,
+    })
+}
+
+fn track_json(id: Option<&str>, release_date: Option<&String>) -> Value {
+    json!({
+        "album": {
+            "album_type": "album",
+            "artists": [],
+            "external_urls": {},
+            "href": null,
+            "id": null,
+            "images": [],
+            "name": "",
+            "release_date": release_date,
+            "release_date_precision": "day",
+        },
+        "artists": [],
+        "disc_number": 1,
+        "duration_ms": 0,
+        "explicit": false,
+        "external_ids": {},
+        "external_urls": {},
+        "href": null,
+        "id": id,
+        "is_local": id.is_none(),
+        "name": id.unwrap_or_default(),
+        "popularity": 0,
+        "preview_url": null,
+        "track_number": 1,
+    })
+}
+
+fn episode_json(id: &str) -> Value {
+    json!({
+        "audio_preview_url": null,
+        "description": "",
+        "duration_ms": 0,
+        "explicit": false,
+        "external_urls": {},
+        "href": "",
+        "id": id,
+        "images": [],
+        "is_externally_hosted": false,
+        "is_playable": true,
+        "language": "en",
+        "languages": ["en"],
+        "name": id,
+        "release_date": "2020-01-01",
+        "release_date_precision": "day",
+        "resume_point": null,
+        "show": {
+            "available_markets": [],
+            "copyrights": [],
+            "description": "",
+            "explicit": false,
+            "external_urls": {},
+            "href": "",
+            "id": "show",
+            "images": [],
+            "is_externally_hosted": null,
+            "languages": [],
+            "media_type": "audio",
+            "name": "",
+            "publisher": "",
+        },
+    })
+}
@@ -0,0 +1,139 @@
+use crate::options::ForeignItemsPolicy;
+use rspotify::model::{PlayableItem, PlaylistItem};
+use rspotify::prelude::PlayableId;
+
+/// Identifies an item in a playlist during an update.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ItemKey {
+    Playable(PlayableId<'static>),
+    /// Local files have no Spotify ID, so they are identified by their
+    /// position in the playlist before the update.
+    Local(usize),
+}
+
+impl ItemKey {
+    pub fn playable(&self) -> Option<PlayableId<'static>> {
+        match self {
+            ItemKey::Playable(id) => Some(id.clone()),
+            ItemKey::Local(_) => None,
+        }
+    }
+}
+
+pub struct Plan {
+    pub current: Vec<Option<ItemKey>>,
+    pub desired: Vec<ItemKey>,
+    /// Local files can not be removed item by item, so the whole playlist
+    /// contents need to be replaced.
+    pub replace: bool,
+}
+
+/// Returns the ID of the given item when it is a track which can be managed
+/// by the automations. Local files and episodes are foreign items.
+pub fn managed_id(item: &PlaylistItem) -> Option<PlayableId<'static>> {
+    match &item.track {
+        Some(PlayableItem::Track(track)) if !item.is_local => track
+            .id
+            .as_ref()
+            .map(|id| PlayableId::Track(id.clone_static())),
+        _ => None,
+    }
+}
+
+fn foreign_key(position: usize, item: &PlaylistItem) -> ItemKey {
+    match &item.track {
+        Some(PlayableItem::Episode(episode)) => {
+            ItemKey::Playable(PlayableId::Episode(episode.id.clone_static()))
+        }
+        _ => ItemKey::Local(position),
+    }
+}
+
+/// Maps the current playlist items and the desired tracks to the inputs of
+/// the diff depending on how foreign items shall be handled. Unless
+/// `can_replace` is set, foreign items are kept instead of being removed, as
+/// removing local files requires replacing all items.
+pub fn plan(
+    items: &[PlaylistItem],
+    desired: &[PlayableId<'static>],
+    policy: ForeignItemsPolicy,
+    can_replace: bool,
+) -> Plan {
+    let mut current = Vec::with_capacity(items.len());
+    let mut foreign = vec![];
+    let mut replace = false;
+
+    for (i, item) in items.iter().enumerate() {
+        if let Some(id) = managed_id(item) {
+            current.push(Some(ItemKey::Playable(id)));
+            continue;
+        }
+
+        let key = foreign_key(i, item);
+        match policy {
+            ForeignItemsPolicy::Keep => current.push(None),
+            ForeignItemsPolicy::Remove if !can_replace => current.push(None),
+            ForeignItemsPolicy::MoveToEnd => {
+                foreign.push(key.clone());
+                current.push(Some(key));
+            }
+            ForeignItemsPolicy::Remove => {
+                replace |= matches!(key, ItemKey::Local(_));
+                current.push(Some(key));
+            }
+        }
+    }
+
+    let desired = desired
+        .iter()
+        .cloned()
+        .map(ItemKey::Playable)
+        .chain(foreign)
+        .collect();
+
+    Plan {
+        current,
+        desired,
+        replace,
+    }
+}
+
+/// Returns the names of the local files in `before` which are missing in
+/// `after`.
+pub fn lost_local_files(before: &[PlaylistItem], after: &[PlaylistItem]) -> Vec<String> {
+    let mut remaining = local_files(after);
+    local_files(before)
+        .into_iter()
+        .filter(|name| match remaining.iter().position(|n| n == name) {
+            Some(i) => {
+                remaining.swap_remove(i);
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
+fn local_files(items: &[PlaylistItem]) -> Vec<String> {
+    items
+        .iter()
+        .filter(|item| item.is_local)
+        .map(|item| match &item.track {
+            Some(PlayableItem::Track(track)) => track.name.clone(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// Returns the keys of all items which can be restored after a failed update.
+/// Local files can not be re-added, so they are left untouched.
+pub fn restorable(items: &[PlaylistItem]) -> Vec<Option<ItemKey>> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match managed_id(item) {
+            Some(id) => Some(ItemKey::Playable(id)),
+            None => Some(foreign_key(i, item)).filter(|key| key.playable().is_some()),
+        })
+        .collect()
+}
@@ -1,35 +1,86 @@
+#[macro_use]
+mod macros;
+
+pub mod api;
+mod client;
+pub mod crypto;
+mod diff;
 pub mod errors;
+pub mod fake;
+mod items;
+mod notify;
+pub mod options;
+mod outcome;
+mod ratelimit;
+pub mod record;
+mod retry;
 
 use self::errors::Error;
+use api::SpotifyApi;
+use chrono::{TimeDelta, Utc};
+pub use client::Client;
+use client::{is_not_found, token_error};
+use diff::{diff, Change};
 use errors::Result;
-use futures::stream::TryStreamExt;
-use futures::{future, StreamExt};
+use items::ItemKey;
+use notify::Notification;
+use options::Options;
+pub use outcome::{PlaylistUpdate, UpdateOutcome};
 use persistence::KV;
-use rspotify::model::{FullPlaylist, FullTrack, PlaylistId, SimplifiedPlaylist, TimeRange};
+use ratelimit::RateLimiter;
+use record::{Automation, PlaylistRecord};
+use rspotify::http::Form;
+use rspotify::model::{
+    FullPlaylist, FullTrack, PlaylistId, PlaylistItem, SavedTrack, SimplifiedPlaylist, TimeRange,
+};
 use rspotify::prelude::{BaseClient, OAuthClient, PlayableId};
-use rspotify::{scopes, AuthCodeSpotify, Config, Credentials, OAuth, Token};
-use std::env;
+use rspotify::{
+    scopes, AuthCodeSpotify, Config, Credentials, OAuth, Token, DEFAULT_API_BASE_URL,
+    DEFAULT_AUTH_BASE_URL,
+};
+use std::collections::HashSet;
 use std::ops::Range;
 use std::sync::Arc;
 
+/// Common prefix of all keys stored by the controller.
+pub const DBKEY_PREFIX: &str = "spotify_automation_";
+
+/// Key of the cached access token. It expires within an hour, so it is not
+/// worth moving along with the rest of the stored state.
+pub const DBKEY_ACCESS_TOKEN: &str = "spotify_automation_access_token";
+
 const DBKEY_REFRESH_TOKEN: &str = "spotify_automation_refresh_token";
 const DBKEY_PLAYLIST_MOSTPLAYED_PREFIX: &str = "spotify_automation_playlist_id";
 const DBKEY_PLAYLIST_TIMERANGE_PREFIX: &str = "spotify_automation_timerange_id";
+const DBKEY_PLAYLIST_DWA_PREFIX: &str = "spotify_automation_dwa_id";
+const DBKEY_MIGRATED: &str = "spotify_automation_unprefixed_keys_migrated";
+const DBKEY_TOKEN_REVOKED: &str = "spotify_automation_refresh_token_revoked";
 
-macro_rules! from_env {
-    ($name:literal) => {
-        env::var($name).map_err(|err| Error::EnvVar { name: $name, err })
-    };
-}
+/// Prefixes of all keys which were stored without a namespace.
+const UNPREFIXED_KEYS: [&str; 4] = [
+    DBKEY_REFRESH_TOKEN,
+    DBKEY_PLAYLIST_MOSTPLAYED_PREFIX,
+    DBKEY_PLAYLIST_TIMERANGE_PREFIX,
+    DBKEY_PLAYLIST_DWA_PREFIX,
+];
+
+const PAGE_SIZE: u32 = 50;
+
+/// How long a cached access token has to remain valid to be reused, so it
+/// doesn't expire during an invocation.
+const ACCESS_TOKEN_MARGIN: TimeDelta = TimeDelta::minutes(5);
 
 pub struct UnauthorizedController<DB: KV> {
     client: AuthCodeSpotify,
     db: Arc<DB>,
+    options: Options,
+    limiter: Arc<RateLimiter>,
 }
 
-pub struct AuthorizedController<DB: KV> {
-    client: AuthCodeSpotify,
+pub struct AuthorizedController<DB: KV, API: SpotifyApi = Client> {
+    client: API,
     db: Arc<DB>,
+    options: Options,
 }
 
 impl<DB: KV> UnauthorizedController<DB> {
@@ -40,6 +91,9 @@ impl<DB: KV> UnauthorizedController<DB> {
         db: DB,
     ) -> UnauthorizedController<DB> {
         let config = Config {
+            // Tokens are refreshed when authorizing, as rspotify would drop a
+            // rotated refresh token.
+            token_refreshing: false,
             ..Default::default()
         };
 
@@ -58,22 +112,42 @@ impl<DB: KV> UnauthorizedController<DB> {
         let creds = Credentials::new(client_id, client_secret);
         let client = AuthCodeSpotify::with_config(creds, oauth, config);
 
+        let options = Options::default();
+        let limiter = Arc::new(options.rate_limiter());
+
         UnauthorizedController {
             client,
             db: Arc::new(db),
+            options,
+            limiter,
         }
     }
 
+    pub fn with_options(mut self, options: Options) -> UnauthorizedController<DB> {
+        let config = &mut self.client.config;
+        config.api_base_url = options
+            .api_base_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_API_BASE_URL.into());
+        config.auth_base_url = options
+            .auth_base_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_AUTH_BASE_URL.into());
+
+        self.limiter = Arc::new(options.rate_limiter());
+        self.options = options;
+        self
+    }
+
     pub fn from_env(db: DB) -> Result<UnauthorizedController<DB>> {
         let client_id = from_env!("SPOTIFY_CLIENTID")?;
         let client_secret = from_env!("SPOTIFY_CLIENTSECRET")?;
         let redirect_uri = from_env!("REDIRECT_URL")?;
-        Ok(UnauthorizedController::new(
-            &client_id,
-            &client_secret,
-            redirect_uri,
-            db,
-        ))
+        let options = Options::from_env()?;
+        Ok(
+            UnauthorizedController::new(&client_id, &client_secret, redirect_uri, db)
+                .with_options(options),
+        )
     }
 
     pub fn get_authorize_url(&self) -> Result<String> {
@@ -86,54 +160,315 @@ impl<DB: KV> UnauthorizedController<DB> {
             .await
             .map_err(|err| Error::AuthorizationFailed(err.into()))?;
 
-        Ok(AuthorizedController {
-            client: self.client.clone(),
-            db: self.db.clone(),
-        })
+        Ok(self.authorized())
     }
 
     pub async fn authorize_with_token(&self, token: String) -> Result<AuthorizedController<DB>> {
-        let token = Token {
-            refresh_token: Some(token),
-            ..Default::default()
+        let token = self.refresh_access_token(token).await?;
+        self.set_token(token).await?;
+        Ok(self.authorized())
+    }
+
+    /// Requests a new access token. Unlike `OAuthClient::refresh_token`, this
+    /// keeps the new refresh token if Spotify has rotated it.
+    async fn refresh_access_token(&self, refresh_token: String) -> Result<Token> {
+        let data = Form::from([
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+        ]);
+        let headers = self.client.creds.auth_headers();
+        let mut token = match self
+            .client
+            .fetch_access_token(&data, headers.as_ref())
+            .await
+        {
+            Ok(token) => token,
+            Err(err) => return Err(token_error(err).await),
         };
 
+        token.refresh_token.get_or_insert(refresh_token);
+        Ok(token)
+    }
+
+    async fn set_token(&self, token: Token) -> Result<()> {
         *(self
             .client
             .token
             .lock()
             .await
             .map_err(|_| Error::LockPoisoned)?) = Some(token);
+        Ok(())
+    }
 
-        self.client.refresh_token().await?;
-
-        Ok(AuthorizedController {
-            client: self.client.clone(),
+    fn authorized(&self) -> AuthorizedController<DB> {
+        AuthorizedController {
+            client: Client::new(
+                self.client.clone(),
+                self.limiter.clone(),
+                self.options.backoff(),
+            ),
             db: self.db.clone(),
-        })
+            options: self.options.clone(),
+        }
     }
 
     pub async fn authorize_from_db(&self) -> Result<AuthorizedController<DB>> {
-        let Some(token) = self.db.get(DBKEY_REFRESH_TOKEN)? else {
+        if self.options.migrate_unprefixed_keys {
+            self.migrate_unprefixed_keys().await?;
+        }
+
+        let Some(refresh_token) = self.load_token().await? else {
             return Err(Error::NoAuthToken);
         };
+        // Spotify is not asked again until the app has been authorized again.
+        if self
+            .db
+            .get(self.options.key(DBKEY_TOKEN_REVOKED))
+            .await?
+            .is_some()
+        {
+            return Err(Error::TokenRevoked);
+        }
+
+        if let Some(mut token) = self.load_access_token().await? {
+            token.refresh_token = Some(refresh_token);
+            self.set_token(token).await?;
+            return Ok(self.authorized());
+        }
+
+        let authorized = match self.authorize_with_token(refresh_token.clone()).await {
+            Err(Error::TokenRevoked) => {
+                self.mark_token_revoked().await?;
+                return Err(Error::TokenRevoked);
+            }
+            res => res?,
+        };
+        if authorized.refresh_token().await? != refresh_token {
+            log::info!("Storing the refresh token rotated by Spotify");
+            authorized.store_token().await?;
+        } else {
+            authorized.cache_access_token().await?;
+        }
+        Ok(authorized)
+    }
+
+    /// Remembers that the stored refresh token has been revoked and sends a
+    /// notification, if configured.
+    async fn mark_token_revoked(&self) -> Result<()> {
+        log::warn!("The stored refresh token has been revoked");
+        self.db.delete(self.options.key(DBKEY_ACCESS_TOKEN)).await?;
+
+        // Only the first invocation noticing the revocation notifies.
+        let marked = self
+            .db
+            .compare_and_swap(
+                self.options.key(DBKEY_TOKEN_REVOKED),
+                None,
+                Utc::now().to_rfc3339(),
+            )
+            .await?;
+        if let (true, Some(url)) = (marked, &self.options.notify_webhook_url) {
+            let notification = Notification {
+                event: "token_revoked",
+                text: "The Spotify authorization of the playlist automation has been revoked. \
+                       Log in again to resume the automations.",
+            };
+            notify::send(url, &notification).await;
+        }
+        Ok(())
+    }
+
+    /// Returns the cached access token unless it is about to expire. Values
+    /// which can't be read are logged and treated as missing, so they are
+    /// replaced after the refresh.
+    async fn load_access_token(&self) -> Result<Option<Token>> {
+        let Some(stored) = self.db.get(self.options.key(DBKEY_ACCESS_TOKEN)).await? else {
+            return Ok(None);
+        };
+
+        let token = match self.decode_access_token(&stored) {
+            Ok(token) => token,
+            Err(err) => {
+                log::warn!("Ignoring the cached access token: {err}");
+                None
+            }
+        };
+        Ok(token.filter(|token| {
+            token
+                .expires_at
+                .is_some_and(|expires_at| expires_at - ACCESS_TOKEN_MARGIN > Utc::now())
+        }))
+    }
+
+    /// Values encrypted with an old key or not encrypted at all are skipped,
+    /// so they are encrypted with the current key when they are replaced.
+    fn decode_access_token(&self, stored: &str) -> Result<Option<Token>> {
+        let json = match &self.options.token_cipher {
+            Some(cipher) => {
+                let decrypted = cipher.decrypt(stored, &self.options.key(DBKEY_ACCESS_TOKEN))?;
+                if decrypted.stale {
+                    return Ok(None);
+                }
+                decrypted.plaintext
+            }
+            None if crypto::is_encrypted(stored) => return Err(Error::NoEncryptionKey),
+            None => stored.to_string(),
+        };
+        let token = serde_json::from_str(&json).map_err(Error::InvalidAccessToken)?;
+        Ok(Some(token))
+    }
+
+    /// Re-encrypts the stored refresh token with the current key, which also
+    /// happens whenever it is read.
+    pub async fn reencrypt_token(&self) -> Result<()> {
+        self.load_token().await?;
+        Ok(())
+    }
+
+    async fn load_token(&self) -> Result<Option<String>> {
+        let key = self.options.key(DBKEY_REFRESH_TOKEN);
+        let Some(stored) = self.db.get(&key).await? else {
+            return Ok(None);
+        };
+
+        let Some(cipher) = &self.options.token_cipher else {
+            if crypto::is_encrypted(&stored) {
+                return Err(Error::NoEncryptionKey);
+            }
+            return Ok(Some(stored));
+        };
+
+        // The namespaced key is authenticated, so a token copied into another
+        // namespace can't be read there.
+        let decrypted = cipher.decrypt(&stored, &key)?;
+        if decrypted.stale {
+            let encrypted = cipher.encrypt(&decrypted.plaintext, &key);
+            // Skipped if another process has stored a new token meanwhile.
+            if self
+                .db
+                .compare_and_swap(&key, Some(&stored), encrypted)
+                .await?
+            {
+                log::info!("Re-encrypted the stored refresh token with the current key");
+            }
+        }
+
+        Ok(Some(decrypted.plaintext))
+    }
+
+    /// Moves the keys stored without a namespace into the configured one and
+    /// returns how many have been moved. This only happens once per
+    /// namespace, and keys which already exist in it are left untouched.
+    pub async fn migrate_unprefixed_keys(&self) -> Result<usize> {
+        if self.options.key_namespace.is_none() {
+            return Ok(0);
+        }
+
+        let marker = self.options.key(DBKEY_MIGRATED);
+        if self.db.get(&marker).await?.is_some() {
+            return Ok(0);
+        }
+
+        let mut moved = 0;
+        for prefix in UNPREFIXED_KEYS {
+            for key in self.db.list_keys(prefix).await? {
+                let Some(mut val) = self.db.get(&key).await? else {
+                    continue;
+                };
+                if key == DBKEY_REFRESH_TOKEN {
+                    val = self.reencrypt_migrated_token(val)?;
+                }
+
+                if self
+                    .db
+                    .compare_and_swap(self.options.key(&key), None, &val)
+                    .await?
+                {
+                    self.db.delete(&key).await?;
+                    moved += 1;
+                } else {
+                    log::warn!("Not migrating key {key}, it already exists in the namespace");
+                }
+            }
+        }
+
+        self.db.set(marker, "true").await?;
+        Ok(moved)
+    }
 
-        self.authorize_with_token(token).await
+    /// Encrypts a refresh token stored without a namespace for the namespaced
+    /// key, which it is authenticated with from then on.
+    fn reencrypt_migrated_token(&self, stored: String) -> Result<String> {
+        if !crypto::is_encrypted(&stored) {
+            return Ok(stored);
+        }
+        let cipher = self
+            .options
+            .token_cipher
+            .as_ref()
+            .ok_or(Error::NoEncryptionKey)?;
+        let decrypted = cipher.decrypt(&stored, DBKEY_REFRESH_TOKEN)?;
+        Ok(cipher.encrypt(&decrypted.plaintext, &self.options.key(DBKEY_REFRESH_TOKEN)))
     }
 }
 
-impl<DB: KV> AuthorizedController<DB> {
-    pub async fn refresh_token(&self) -> Result<String> {
-        let token = self.client.get_token();
-        let token = token.lock().await.map_err(|_| Error::LockPoisoned)?;
-        let token = token.to_owned().ok_or(Error::NoAuthToken)?;
+impl<DB: KV, API: SpotifyApi> AuthorizedController<DB, API> {
+    /// Creates a controller which uses the given API client, e.g. a
+    /// [`FakeSpotify`](fake::FakeSpotify) in tests.
+    pub fn with_api(client: API, db: DB, options: Options) -> AuthorizedController<DB, API> {
+        AuthorizedController {
+            client,
+            db: Arc::new(db),
+            options,
+        }
+    }
 
+    /// Returns the number of requests sent to the Spotify API since this
+    /// controller has been authorized.
+    pub fn api_calls(&self) -> usize {
+        self.client.calls()
+    }
+
+    pub async fn refresh_token(&self) -> Result<String> {
+        let token = self.client.token().await?.ok_or(Error::NoAuthToken)?;
         token.refresh_token.ok_or(Error::NoAuthToken)
     }
 
     pub async fn store_token(&self) -> Result<()> {
-        let token = self.refresh_token().await?;
-        self.db.set(DBKEY_REFRESH_TOKEN, token)?;
+        let key = self.options.key(DBKEY_REFRESH_TOKEN);
+        let mut token = self.refresh_token().await?;
+        if let Some(cipher) = &self.options.token_cipher {
+            token = cipher.encrypt(&token, &key);
+        }
+        self.db.set(key, token).await?;
+        self.db
+            .delete(self.options.key(DBKEY_TOKEN_REVOKED))
+            .await?;
+        self.cache_access_token().await
+    }
+
+    /// Stores the access token until it expires, so it is reused instead of
+    /// being refreshed on every invocation.
+    async fn cache_access_token(&self) -> Result<()> {
+        let Some(mut token) = self.client.token().await? else {
+            return Ok(());
+        };
+        let Some(ttl) = token.expires_at.and_then(|expires_at| {
+            (expires_at - ACCESS_TOKEN_MARGIN - Utc::now())
+                .to_std()
+                .ok()
+        }) else {
+            return Ok(());
+        };
+
+        // The refresh token is only stored under its own key.
+        token.refresh_token = None;
+        let key = self.options.key(DBKEY_ACCESS_TOKEN);
+        let mut value = serde_json::to_string(&token).map_err(Error::InvalidAccessToken)?;
+        if let Some(cipher) = &self.options.token_cipher {
+            value = cipher.encrypt(&value, &key);
+        }
+        self.db.set_with_ttl(key, value, ttl).await?;
         Ok(())
     }
 
@@ -142,12 +477,23 @@ impl<DB: KV> AuthorizedController<DB> {
         time_range: Option<TimeRange>,
         limit: Option<usize>,
     ) -> Result<Vec<FullTrack>> {
-        let top_tracks = self.client.current_user_top_tracks(time_range);
+        let limit = limit.unwrap_or(100);
+        let mut tracks = Vec::with_capacity(limit);
 
-        let tracks: std::result::Result<Vec<_>, _> =
-            top_tracks.take(limit.unwrap_or(100)).try_collect().await;
+        while tracks.len() < limit {
+            let page = self
+                .client
+                .current_user_top_tracks(time_range, PAGE_SIZE, tracks.len() as u32)
+                .await?;
+            let done = page.next.is_none() || page.items.is_empty();
+            tracks.extend(page.items);
+            if done {
+                break;
+            }
+        }
 
-        Ok(tracks?)
+        tracks.truncate(limit);
+        Ok(tracks)
     }
 
     pub async fn create_playlist(
@@ -156,59 +502,210 @@ impl<DB: KV> AuthorizedController<DB> {
         description: Option<&str>,
     ) -> Result<FullPlaylist> {
         let me = self.client.current_user().await?;
-        let playlist = self
-            .client
-            .user_playlist_create(me.id, name, Some(false), Some(false), description)
-            .await?;
-        Ok(playlist)
+        self.client.create_playlist(me.id, name, description).await
     }
 
     pub async fn update_playlist(
         &self,
         id: PlaylistId<'_>,
         items: Vec<PlayableId<'_>>,
-    ) -> Result<()> {
-        let current_items = self.client.playlist_items(id.clone(), None, None);
-        let current_items: std::result::Result<Vec<_>, _> = current_items.try_collect().await;
-        let current_items = current_items?;
+    ) -> Result<UpdateOutcome> {
+        let update = self.sync_playlist(id, items).await?;
+        Ok(update.outcome)
+    }
 
-        let current_item_ids: Vec<_> = current_items
-            .iter()
-            .filter_map(|i| i.track.as_ref())
-            .filter_map(|i| i.id())
-            .collect();
+    async fn sync_playlist(
+        &self,
+        id: PlaylistId<'_>,
+        items: Vec<PlayableId<'_>>,
+    ) -> Result<PlaylistUpdate> {
+        let (snapshot_id, current_items) = self.playlist_snapshot(id.clone()).await?;
+        let items: Vec<_> = items.iter().map(|id| id.clone_static()).collect();
 
-        for chunks in current_item_ids.chunks(100) {
-            let chunks = chunks.iter().map(|id| id.clone_static());
-            self.client
-                .playlist_remove_all_occurrences_of_items(id.clone_static(), chunks, None)
-                .await?;
+        self.update_playlist_items(id, snapshot_id, &current_items, &items, true)
+            .await
+    }
+
+    async fn update_playlist_items(
+        &self,
+        id: PlaylistId<'_>,
+        snapshot_id: String,
+        current_items: &[PlaylistItem],
+        items: &[PlayableId<'static>],
+        can_replace: bool,
+    ) -> Result<PlaylistUpdate> {
+        let update = |outcome, snapshot_id| PlaylistUpdate {
+            id: id.clone_static(),
+            outcome,
+            snapshot_id,
+            tracks: items.len(),
+        };
+
+        let plan = items::plan(
+            current_items,
+            items,
+            self.options.foreign_items,
+            can_replace,
+        );
+        if plan.current.iter().flatten().eq(plan.desired.iter()) {
+            return Ok(update(UpdateOutcome::Unchanged, Some(snapshot_id)));
+        }
+
+        let res = match plan.replace {
+            // Replacing the items does not return a snapshot.
+            true => self.replace_items(id.clone(), items).await.map(|_| None),
+            false => {
+                let changes = diff(&plan.current, &plan.desired);
+                self.apply_changes(id.clone(), snapshot_id, changes)
+                    .await
+                    .map(Some)
+            }
+        };
+
+        match res {
+            Ok(snapshot_id) => Ok(update(UpdateOutcome::Updated, snapshot_id)),
+            Err(err) => Err(self.rollback_playlist(id, current_items, err).await),
         }
+    }
+
+    async fn replace_items(&self, id: PlaylistId<'_>, items: &[PlayableId<'static>]) -> Result<()> {
+        let mut chunks = items.chunks(100);
+
+        let first = chunks.next().unwrap_or_default();
+        self.client
+            .playlist_replace_items(id.clone(), first)
+            .await?;
 
-        for chunks in items.chunks(100) {
-            let chunks = chunks.iter().map(|id| id.clone_static());
+        for chunk in chunks {
             self.client
-                .playlist_add_items(id.clone_static(), chunks, None)
+                .playlist_add_items(id.clone(), chunk, None)
                 .await?;
         }
 
         Ok(())
     }
 
-    pub async fn update_top_songs_playlist<'a, T: AsRef<str>>(
-        &'a self,
-        id: Option<&'a str>,
+    async fn apply_changes(
+        &self,
+        id: PlaylistId<'_>,
+        mut snapshot_id: String,
+        changes: Vec<Change<ItemKey>>,
+    ) -> Result<String> {
+        for change in changes {
+            match change {
+                Change::Remove(removals) => {
+                    // Local files are never removed or inserted, see
+                    // items::plan, so only playable items are left here.
+                    let removals: Vec<_> = removals
+                        .into_iter()
+                        .filter_map(|(pos, key)| key.playable().map(|id| (pos, id)))
+                        .collect();
+
+                    for chunk in removals.chunks(100) {
+                        snapshot_id = self
+                            .client
+                            .playlist_remove_items(id.clone(), chunk, &snapshot_id)
+                            .await?;
+                    }
+                }
+                Change::Move { from, to } => {
+                    snapshot_id = self
+                        .client
+                        .playlist_reorder_item(id.clone(), from, to, &snapshot_id)
+                        .await?;
+                }
+                Change::Insert { position, items } => {
+                    let items: Vec<_> = items.iter().filter_map(ItemKey::playable).collect();
+                    for (i, chunk) in items.chunks(100).enumerate() {
+                        let position = (position + i * 100) as u32;
+                        snapshot_id = self
+                            .client
+                            .playlist_add_items(id.clone(), chunk, Some(position))
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        Ok(snapshot_id)
+    }
+
+    async fn rollback_playlist(
+        &self,
+        id: PlaylistId<'_>,
+        previous_items: &[PlaylistItem],
+        err: Error,
+    ) -> Error {
+        let previous: Vec<_> = items::restorable(previous_items)
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let res = async {
+            let (snapshot_id, current_items) = self.playlist_snapshot(id.clone()).await?;
+            let changes = diff(&items::restorable(&current_items), &previous);
+            self.apply_changes(id, snapshot_id, changes).await?;
+            // Local files removed by replacing the items can't be re-added.
+            Ok(items::lost_local_files(previous_items, &current_items))
+        }
+        .await;
+
+        match res {
+            Ok(lost) if lost.is_empty() => Error::PlaylistUpdateRolledBack(Box::new(err)),
+            Ok(lost) => Error::PlaylistLocalFilesLost {
+                err: Box::new(err),
+                lost,
+            },
+            Err(rollback_err) => Error::PlaylistPartiallyUpdated {
+                err: Box::new(err),
+                rollback_err: Box::new(rollback_err),
+            },
+        }
+    }
+
+    async fn playlist_snapshot(&self, id: PlaylistId<'_>) -> Result<(String, Vec<PlaylistItem>)> {
+        let playlist = self.client.playlist(id.clone()).await?;
+        let mut items = playlist.tracks.items;
+        let mut next = playlist.tracks.next;
+
+        while next.is_some() {
+            let page = self
+                .client
+                .playlist_items(id.clone(), items.len() as u32)
+                .await?;
+            if page.items.is_empty() {
+                break;
+            }
+            items.extend(page.items);
+            next = page.next;
+        }
+
+        Ok((playlist.snapshot_id, items))
+    }
+
+    pub async fn update_top_songs_playlist<T: AsRef<str>>(
+        &self,
+        id: Option<&str>,
         name: &str,
         time_range: Option<T>,
         limit: Option<usize>,
-    ) -> Result<PlaylistId<'a>> {
+    ) -> Result<PlaylistUpdate> {
         let time_range = time_range.map(time_range_from_str).transpose()?;
 
-        let playlist_id: PlaylistId<'a> = match id {
-            Some(id) => PlaylistId::from_id_or_uri(id)?,
-            None => self.create_playlist(name, None).await?.id,
+        let playlist_id = match id {
+            Some(id) => PlaylistId::from_id_or_uri(id)?.clone_static(),
+            None => self.find_or_create_playlist(name).await?,
         };
 
+        self.sync_top_songs(playlist_id, time_range, limit).await
+    }
+
+    async fn sync_top_songs(
+        &self,
+        playlist_id: PlaylistId<'static>,
+        time_range: Option<TimeRange>,
+        limit: Option<usize>,
+    ) -> Result<PlaylistUpdate> {
         let top_songs = self
             .get_top_songs(time_range, limit)
             .await?
@@ -218,9 +715,7 @@ impl<DB: KV> AuthorizedController<DB> {
             .map(|v| v.into())
             .collect();
 
-        self.update_playlist(playlist_id.clone(), top_songs).await?;
-
-        Ok(playlist_id)
+        self.sync_playlist(playlist_id, top_songs).await
     }
 
     pub async fn update_mostplayed_playlists<I, E, N>(
@@ -228,33 +723,40 @@ impl<DB: KV> AuthorizedController<DB> {
         time_ranges: I,
         name_prefix: N,
         limit: Option<usize>,
-    ) -> Result<Vec<String>>
+    ) -> Result<Vec<PlaylistUpdate>>
     where
         I: Iterator<Item = E>,
         E: AsRef<str>,
         N: AsRef<str>,
     {
-        let mut ids = Vec::with_capacity(3);
+        let mut updates = Vec::with_capacity(3);
 
         for time_range in time_ranges {
             let time_range = time_range.as_ref();
-            let store_key = format!("{DBKEY_PLAYLIST_MOSTPLAYED_PREFIX}:{time_range}");
-            let playlist_id = self.db.get(store_key)?;
+            // Parsed before the playlist is looked up, so nothing is created
+            // for an invalid time range.
+            let parsed_time_range = time_range_from_str(time_range)?;
+            let store_key = format!(
+                "{DBKEY_PLAYLIST_MOSTPLAYED_PREFIX}:{}:{time_range}",
+                name_prefix.as_ref()
+            );
             let playlist_name = format!("{} ({} Term)", name_prefix.as_ref(), title(time_range));
-
-            let id = self
-                .update_top_songs_playlist(
-                    playlist_id.as_deref(),
-                    &playlist_name,
-                    Some(time_range),
-                    limit,
-                )
+            let automation = Automation::MostPlayed {
+                name_prefix: name_prefix.as_ref().to_string(),
+                time_range: time_range.to_string(),
+                limit,
+            };
+            let mut record = self
+                .get_managed_playlist(&store_key, &playlist_name, automation)
                 .await?;
 
-            ids.push(id.to_string());
+            let res = self
+                .sync_top_songs(record.id()?, Some(parsed_time_range), limit)
+                .await;
+            updates.push(self.store_run(&store_key, &mut record, res).await?);
         }
 
-        Ok(ids)
+        Ok(updates)
     }
 
     pub async fn find_playlist<P>(&self, preticate: P) -> Result<SimplifiedPlaylist>
@@ -262,12 +764,11 @@ impl<DB: KV> AuthorizedController<DB> {
         P: FnMut(&&SimplifiedPlaylist) -> bool + Copy,
     {
         let mut offset = 0;
-        const PAGE_SIZE: u32 = 50;
 
         loop {
             let playlists = self
                 .client
-                .current_user_playlists_manual(Some(PAGE_SIZE), Some(offset))
+                .current_user_playlists(PAGE_SIZE, offset)
                 .await?;
 
             if playlists.items.is_empty() {
@@ -288,45 +789,244 @@ impl<DB: KV> AuthorizedController<DB> {
         &self,
         year_range: Range<u32>,
         playlist_name: impl AsRef<str>,
-    ) -> Result<PlaylistId> {
-        let iter = self.client.current_user_saved_tracks(None).filter(|t| {
-            future::ready(t.as_ref().is_ok_and(|t| {
+    ) -> Result<PlaylistUpdate> {
+        let store_key = format!(
+            "{}:{}-{}",
+            DBKEY_PLAYLIST_TIMERANGE_PREFIX, year_range.start, year_range.end
+        );
+
+        let automation = Automation::TimeRange {
+            from: year_range.start,
+            to: year_range.end,
+        };
+        let mut record = self
+            .get_managed_playlist(&store_key, playlist_name.as_ref(), automation)
+            .await?;
+
+        let res = self.sync_timerange(record.id()?, year_range).await;
+        self.store_run(&store_key, &mut record, res).await
+    }
+
+    async fn sync_timerange(
+        &self,
+        id: PlaylistId<'static>,
+        year_range: Range<u32>,
+    ) -> Result<PlaylistUpdate> {
+        let item_ids = self
+            .saved_tracks()
+            .await?
+            .into_iter()
+            .filter(|t| {
                 t.track
                     .album
                     .release_date
                     .as_ref()
                     .is_some_and(|date| year(date).is_ok_and(|year| year_range.contains(&year)))
-            }))
-        });
-
-        let items: std::result::Result<Vec<_>, _> = iter.try_collect().await;
-
-        let item_ids = items?
-            .iter()
-            .cloned()
+            })
             .map(|p| p.track)
             .filter_map(|t| t.id.as_ref().map(|id| id.clone_static()))
             .map(PlayableId::from)
             .collect();
 
-        let store_key = format!(
-            "{}:{}-{}",
-            DBKEY_PLAYLIST_TIMERANGE_PREFIX, year_range.start, year_range.end
-        );
+        self.sync_playlist(id, item_ids).await
+    }
+
+    pub async fn update_dwa_playlist(
+        &self,
+        dw_name: impl AsRef<str>,
+        dwa_name: impl AsRef<str>,
+    ) -> Result<PlaylistUpdate> {
+        let dw_name = dw_name.as_ref();
+        let dwa_name = dwa_name.as_ref();
+
+        let dw_playlist = self.find_playlist(|p| p.name == dw_name).await?;
+
+        let store_key = format!("{DBKEY_PLAYLIST_DWA_PREFIX}:{dwa_name}");
+
+        let automation = Automation::Dwa {
+            source: dw_name.to_string(),
+        };
+        let mut record = self
+            .get_managed_playlist(&store_key, dwa_name, automation)
+            .await?;
 
-        let playlist_id = self.db.get(&store_key)?;
-        let playlist_id = match playlist_id.as_deref() {
-            Some(id) => PlaylistId::from_id_or_uri(id)?,
+        let res = self.sync_archive(record.id()?, dw_playlist.id).await;
+        self.store_run(&store_key, &mut record, res).await
+    }
+
+    async fn sync_archive(
+        &self,
+        playlist_id: PlaylistId<'static>,
+        dw_id: PlaylistId<'static>,
+    ) -> Result<PlaylistUpdate> {
+        let (snapshot_id, current_items) = self.playlist_snapshot(playlist_id.clone()).await?;
+        let mut archived_items: Vec<_> =
+            current_items.iter().filter_map(items::managed_id).collect();
+        let mut archived_ids: HashSet<_> = archived_items.iter().cloned().collect();
+
+        let new_items = self
+            .playlist_item_ids(dw_id)
+            .await?
+            .into_iter()
+            .filter(|id| archived_ids.insert(id.clone()));
+        archived_items.extend(new_items);
+
+        // Replacing the items would reset the dates they have been added to
+        // the archive, so local files are never removed from it.
+        self.update_playlist_items(
+            playlist_id,
+            snapshot_id,
+            &current_items,
+            &archived_items,
+            false,
+        )
+        .await
+    }
+
+    pub async fn is_playlist_available(&self, id: PlaylistId<'_>) -> Result<bool> {
+        let playlist = match self.client.playlist(id.clone()).await {
+            Ok(v) => v,
+            Err(err) if is_not_found(&err) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        let me = self.client.current_user().await?;
+        if playlist.owner.id != me.id {
+            return Ok(false);
+        }
+
+        self.client.playlist_check_follow(id, me.id).await
+    }
+
+    async fn saved_tracks(&self) -> Result<Vec<SavedTrack>> {
+        let mut tracks = vec![];
+
+        loop {
+            let page = self
+                .client
+                .current_user_saved_tracks(PAGE_SIZE, tracks.len() as u32)
+                .await?;
+            let done = page.next.is_none() || page.items.is_empty();
+            tracks.extend(page.items);
+            if done {
+                break;
+            }
+        }
+
+        Ok(tracks)
+    }
+
+    async fn get_managed_playlist(
+        &self,
+        store_key: &str,
+        name: &str,
+        automation: Automation,
+    ) -> Result<PlaylistRecord> {
+        if let Some(value) = self.db.get(self.options.key(store_key)).await? {
+            let mut record = PlaylistRecord::parse(&value, automation.clone(), name)?;
+
+            if self.is_playlist_available(record.id()?).await? {
+                // The parameters might have changed since the last run.
+                record.automation = automation;
+                record.name = name.to_string();
+                return Ok(record);
+            }
+
+            if !self.options.recreate_deleted_playlists {
+                return Err(Error::PlaylistDoesNotExist);
+            }
+        }
+
+        let record = match self.find_adoptable_playlist(name).await? {
+            Some(id) => {
+                // The playlist has not been created by the app.
+                let mut record = PlaylistRecord::new(automation, name, &id);
+                record.created_at = None;
+                record
+            }
             None => {
-                let id = self.create_playlist(playlist_name.as_ref(), None).await?.id;
-                self.db.set(store_key, id.to_string())?;
-                id
+                let id = self.create_playlist(name, None).await?.id;
+                PlaylistRecord::new(automation, name, &id)
+            }
+        };
+        // Store the playlist right away, so it is not created again when
+        // the update fails.
+        self.store_record(store_key, &record).await?;
+
+        Ok(record)
+    }
+
+    /// Stores the result of a run in the record of the playlist, also when
+    /// the run failed.
+    async fn store_run(
+        &self,
+        store_key: &str,
+        record: &mut PlaylistRecord,
+        res: Result<PlaylistUpdate>,
+    ) -> Result<PlaylistUpdate> {
+        let err = match res {
+            Ok(update) => {
+                record.record_run(&update);
+                self.store_record(store_key, record).await?;
+                return Ok(update);
             }
+            Err(err) => err,
         };
 
-        self.update_playlist(playlist_id.clone(), item_ids).await?;
+        record.record_failure(&err);
+        // The error of the run is reported rather than the one of storing it.
+        if let Err(store_err) = self.store_record(store_key, record).await {
+            log::warn!(
+                "Storing the failed run of {} failed: {store_err}",
+                record.name
+            );
+        }
+        Err(err)
+    }
+
+    async fn store_record(&self, store_key: &str, record: &PlaylistRecord) -> Result<()> {
+        let value = serde_json::to_string(record)?;
+        self.db.set(self.options.key(store_key), value).await?;
+        Ok(())
+    }
+
+    async fn find_or_create_playlist(&self, name: &str) -> Result<PlaylistId<'static>> {
+        match self.find_adoptable_playlist(name).await? {
+            Some(id) => Ok(id),
+            None => Ok(self.create_playlist(name, None).await?.id),
+        }
+    }
+
+    /// Returns the user's playlist with the given name if existing playlists
+    /// shall be adopted.
+    async fn find_adoptable_playlist(&self, name: &str) -> Result<Option<PlaylistId<'static>>> {
+        if !self.options.adopt_existing_playlists {
+            return Ok(None);
+        }
+
+        let me = self.client.current_user().await?;
+        let res = self
+            .find_playlist(|p| p.name == name && p.owner.id == me.id)
+            .await;
+
+        match res {
+            Ok(playlist) => Ok(Some(playlist.id)),
+            Err(Error::NoPlaylistFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn playlist_item_ids(&self, id: PlaylistId<'_>) -> Result<Vec<PlayableId<'static>>> {
+        let (_, items) = self.playlist_snapshot(id).await?;
+
+        let ids = items
+            .iter()
+            .filter_map(|i| i.track.as_ref())
+            .filter_map(|i| i.id())
+            .map(|id| id.clone_static())
+            .collect();
 
-        Ok(playlist_id.clone_static())
+        Ok(ids)
     }
 }
 
@@ -360,6 +1060,616 @@ fn year(v: &str) -> Result<u32> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use fake::{FakeItem, FakeSpotify};
+    use options::ForeignItemsPolicy;
+    use persistence::memory::Memory;
+    use rspotify::prelude::Id;
+
+    fn controller(
+        fake: &FakeSpotify,
+        options: Options,
+    ) -> AuthorizedController<Memory, FakeSpotify> {
+        AuthorizedController::with_api(fake.clone(), Memory::new(), options)
+    }
+
+    async fn record(db: &Memory, key: &str) -> PlaylistRecord {
+        let value = db.get(key).await.unwrap().unwrap();
+        serde_json::from_str(&value).unwrap()
+    }
+
+    fn tracks(ids: &[&str]) -> Vec<FakeItem> {
+        ids.iter()
+            .map(|id| FakeItem::Track(id.to_string()))
+            .collect()
+    }
+
+    fn playable(ids: &[&str]) -> Vec<PlayableId<'static>> {
+        ids.iter()
+            .map(|id| {
+                PlayableId::Track(rspotify::model::TrackId::from_id(*id).unwrap()).clone_static()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_update_mostplayed_playlists() {
+        let fake = FakeSpotify::new("me");
+        fake.set_top_tracks(TimeRange::ShortTerm, &["a", "b", "c"]);
+        fake.set_top_tracks(TimeRange::LongTerm, &["c", "d"]);
+        let ctrl = controller(&fake, Options::default());
+
+        let updates = ctrl
+            .update_mostplayed_playlists(["short", "long"].iter(), "Top", None)
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 2);
+        assert!(updates.iter().all(|u| u.outcome == UpdateOutcome::Updated));
+        assert_eq!(fake.items(&updates[0].id), tracks(&["a", "b", "c"]));
+        assert_eq!(fake.items(&updates[1].id), tracks(&["c", "d"]));
+        assert_eq!(
+            fake.playlist_ids("Top (Short Term)"),
+            vec![updates[0].id.clone()]
+        );
+        let long = record(
+            &ctrl.db,
+            &format!("{DBKEY_PLAYLIST_MOSTPLAYED_PREFIX}:Top:long"),
+        )
+        .await;
+        assert_eq!(long.playlist_id, updates[1].id.to_string());
+        assert_eq!(long.name, "Top (Long Term)");
+        assert_eq!(
+            long.automation,
+            Automation::MostPlayed {
+                name_prefix: "Top".into(),
+                time_range: "long".into(),
+                limit: None,
+            }
+        );
+        assert_eq!(long.track_count, Some(2));
+        assert_eq!(long.last_outcome, Some(UpdateOutcome::Updated));
+        assert!(long.created_at.is_some() && long.last_run_at >= long.created_at);
+
+        fake.set_top_tracks(TimeRange::ShortTerm, &["c", "a", "e"]);
+        let writes = fake.writes();
+        let again = ctrl
+            .update_mostplayed_playlists(["short", "long"].iter(), "Top", None)
+            .await
+            .unwrap();
+        assert_eq!(again[0].id, updates[0].id);
+        assert_eq!(again[0].outcome, UpdateOutcome::Updated);
+        assert_eq!(again[1].outcome, UpdateOutcome::Unchanged);
+        assert_eq!(fake.items(&again[0].id), tracks(&["c", "a", "e"]));
+        // b is removed, c is moved and e is inserted.
+        assert_eq!(fake.writes() - writes, 3);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_time_range() {
+        let fake = FakeSpotify::new("me");
+        let ctrl = controller(&fake, Options::default());
+
+        assert!(matches!(
+            ctrl.update_mostplayed_playlists(["bogus"].iter(), "Top", None)
+                .await,
+            Err(Error::InvalidTimeRange)
+        ));
+        assert!(fake.playlist_ids("Top (Bogus Term)").is_empty());
+        assert!(ctrl.db.dump().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_top_songs() {
+        let fake = FakeSpotify::new("me");
+        let ids: Vec<_> = (0..120).map(|i| format!("t{i}")).collect();
+        let ids: Vec<_> = ids.iter().map(String::as_str).collect();
+        fake.set_top_tracks(TimeRange::MediumTerm, &ids);
+        let ctrl = controller(&fake, Options::default());
+
+        let songs = ctrl.get_top_songs(None, None).await.unwrap();
+        assert_eq!(songs.len(), 100);
+        assert_eq!(fake.calls(), 2);
+
+        let songs = ctrl.get_top_songs(None, Some(130)).await.unwrap();
+        assert_eq!(songs.len(), 120);
+        assert_eq!(
+            songs[119].id.as_ref().unwrap().to_string(),
+            "spotify:track:t119"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_timerange_playlist() {
+        let fake = FakeSpotify::new("me");
+        fake.add_track("a", "1999-12-31");
+        fake.add_track("b", "2000");
+        fake.add_track("c", "2009-06-01");
+        fake.add_track("d", "2010-01-01");
+        fake.set_saved_tracks(&["a", "b", "c", "d", "e"]);
+        let ctrl = controller(&fake, Options::default());
+
+        let update = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        assert_eq!(update.outcome, UpdateOutcome::Updated);
+        assert_eq!(fake.items(&update.id), tracks(&["b", "c"]));
+
+        let again = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        assert_eq!(again.id, update.id);
+        assert_eq!(again.outcome, UpdateOutcome::Unchanged);
+
+        let key = format!("{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010");
+        fake.add_track("f", "2005");
+        fake.set_saved_tracks(&["b", "c", "f"]);
+        fake.fail_write(0);
+        assert!(ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .is_err());
+        let failed = record(&ctrl.db, &key).await;
+        assert!(failed.last_error.is_some());
+        assert_eq!(failed.last_outcome, None);
+        assert_eq!(failed.track_count, Some(2));
+
+        ctrl.update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        let succeeded = record(&ctrl.db, &key).await;
+        assert_eq!(succeeded.last_error, None);
+        assert_eq!(succeeded.last_outcome, Some(UpdateOutcome::Updated));
+        assert!(succeeded.last_run_at > failed.last_run_at);
+    }
+
+    #[tokio::test]
+    async fn test_update_dwa_playlist() {
+        let fake = FakeSpotify::new("me");
+        let dw = fake.add_playlist("Discover Weekly", "spotify", tracks(&["a", "b"]));
+        let ctrl = controller(&fake, Options::default());
+
+        let update = ctrl
+            .update_dwa_playlist("Discover Weekly", "Archive")
+            .await
+            .unwrap();
+        assert_eq!(fake.items(&update.id), tracks(&["a", "b"]));
+
+        fake.remove_playlist(&dw);
+        assert!(matches!(
+            ctrl.update_dwa_playlist("Discover Weekly", "Archive").await,
+            Err(Error::NoPlaylistFound)
+        ));
+
+        fake.add_playlist("Discover Weekly", "spotify", tracks(&["b", "c"]));
+        let update = ctrl
+            .update_dwa_playlist("Discover Weekly", "Archive")
+            .await
+            .unwrap();
+        assert_eq!(update.outcome, UpdateOutcome::Updated);
+        assert_eq!(fake.items(&update.id), tracks(&["a", "b", "c"]));
+
+        let again = ctrl
+            .update_dwa_playlist("Discover Weekly", "Archive")
+            .await
+            .unwrap();
+        assert_eq!(again.outcome, UpdateOutcome::Unchanged);
+        assert_eq!(fake.playlist_ids("Archive"), vec![update.id]);
+
+        let options = Options {
+            foreign_items: ForeignItemsPolicy::Remove,
+            adopt_existing_playlists: true,
+            ..Default::default()
+        };
+        let ctrl = controller(&fake, options);
+        fake.add_playlist(
+            "Foreign Archive",
+            "me",
+            vec![
+                FakeItem::Local("x".into()),
+                FakeItem::Track("a".into()),
+                FakeItem::Episode("e".into()),
+            ],
+        );
+        let update = ctrl
+            .update_dwa_playlist("Discover Weekly", "Foreign Archive")
+            .await
+            .unwrap();
+        // The archive isn't replaced, so foreign items are kept despite the
+        // policy.
+        assert_eq!(
+            fake.items(&update.id),
+            vec![
+                FakeItem::Local("x".into()),
+                FakeItem::Track("a".into()),
+                FakeItem::Track("b".into()),
+                FakeItem::Track("c".into()),
+                FakeItem::Episode("e".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_playlist() {
+        let fake = FakeSpotify::new("me");
+        for i in 0..60 {
+            fake.add_playlist(&format!("p{i}"), "me", vec![]);
+        }
+        let ctrl = controller(&fake, Options::default());
+
+        let playlist = ctrl.find_playlist(|p| p.name == "p55").await.unwrap();
+        assert_eq!(playlist.name, "p55");
+        assert!(matches!(
+            ctrl.find_playlist(|p| p.name == "p60").await,
+            Err(Error::NoPlaylistFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_deleted_playlists() {
+        let fake = FakeSpotify::new("me");
+        fake.add_track("a", "2001");
+        fake.set_saved_tracks(&["a"]);
+        let ctrl = controller(&fake, Options::default());
+
+        let update = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        fake.unfollow_playlist(&update.id);
+        assert!(matches!(
+            ctrl.update_timerange_playlist(2000..2010, "2000s").await,
+            Err(Error::PlaylistDoesNotExist)
+        ));
+
+        let options = Options {
+            recreate_deleted_playlists: true,
+            ..Default::default()
+        };
+        let ctrl = AuthorizedController { options, ..ctrl };
+        let recreated = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        assert_ne!(recreated.id, update.id);
+        assert_eq!(fake.items(&recreated.id), tracks(&["a"]));
+        assert_eq!(fake.playlist_ids("2000s"), vec![recreated.id]);
+    }
+
+    #[tokio::test]
+    async fn test_stored_playlist() {
+        let fake = FakeSpotify::new("me");
+        let stored = fake.add_playlist("Old Name", "me", tracks(&["x"]));
+        fake.add_track("a", "2001");
+        fake.set_saved_tracks(&["a"]);
+        let db = Memory::from(std::collections::HashMap::from([(
+            format!("{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+            stored.id().to_string(),
+        )]));
+        let ctrl = AuthorizedController::with_api(fake.clone(), db, Options::default());
+
+        let update = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        assert_eq!(update.id, stored);
+        assert_eq!(fake.items(&stored), tracks(&["a"]));
+        assert!(fake.playlist_ids("2000s").is_empty());
+
+        // The bare ID stored before has been replaced by a record.
+        let record = record(
+            &ctrl.db,
+            &format!("{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+        )
+        .await;
+        assert_eq!(record.playlist_id, stored.to_string());
+        assert_eq!(record.name, "2000s");
+        assert_eq!(record.created_at, None);
+        assert!(record.last_run_at.is_some());
+        assert_eq!(record.snapshot_id, update.snapshot_id);
+        assert_eq!(record.track_count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_key_namespace() {
+        let fake = FakeSpotify::new("me");
+        fake.add_track("a", "2001");
+        fake.set_saved_tracks(&["a"]);
+        let options = Options {
+            key_namespace: Some("staging".into()),
+            ..Default::default()
+        };
+        let ctrl = controller(&fake, options);
+
+        let update = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        let key = format!("staging:{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010");
+        assert_eq!(ctrl.db.dump().keys().collect::<Vec<_>>(), [&key]);
+        assert_eq!(
+            record(&ctrl.db, &key).await.playlist_id,
+            update.id.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_unprefixed_keys() {
+        let key = |key: &str| key.to_string();
+        let db = Memory::from(std::collections::HashMap::from([
+            (key(DBKEY_REFRESH_TOKEN), key("token")),
+            (format!("{DBKEY_PLAYLIST_DWA_PREFIX}:Weekly"), key("dwa")),
+            (
+                format!("{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+                key("old"),
+            ),
+            (
+                format!("prod:{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+                key("new"),
+            ),
+            (key("unrelated"), key("x")),
+        ]));
+        let options = Options {
+            key_namespace: Some("prod".into()),
+            migrate_unprefixed_keys: true,
+            ..Default::default()
+        };
+        let ctrl = UnauthorizedController::new("id", "secret", "http://localhost".into(), db)
+            .with_options(options);
+
+        assert_eq!(ctrl.migrate_unprefixed_keys().await.unwrap(), 2);
+        assert_eq!(ctrl.migrate_unprefixed_keys().await.unwrap(), 0);
+
+        assert_eq!(
+            ctrl.db.dump(),
+            std::collections::HashMap::from([
+                (format!("prod:{DBKEY_MIGRATED}"), key("true")),
+                (format!("prod:{DBKEY_REFRESH_TOKEN}"), key("token")),
+                (
+                    format!("prod:{DBKEY_PLAYLIST_DWA_PREFIX}:Weekly"),
+                    key("dwa")
+                ),
+                (
+                    format!("prod:{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+                    key("new")
+                ),
+                (
+                    format!("{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+                    key("old")
+                ),
+                (key("unrelated"), key("x")),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_encryption() {
+        const KEY_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        const KEY_B: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBA=";
+        let with_keys = |current: &str, old: &[&str]| Options {
+            token_cipher: Some(crypto::Cipher::new(current, old).unwrap()),
+            ..Default::default()
+        };
+        let stored =
+            |ctrl: &UnauthorizedController<Memory>| ctrl.db.dump()[DBKEY_REFRESH_TOKEN].clone();
+
+        let db = Memory::from(std::collections::HashMap::from([(
+            DBKEY_REFRESH_TOKEN.to_string(),
+            "token".to_string(),
+        )]));
+        let ctrl = UnauthorizedController::new("id", "secret", "http://localhost".into(), db)
+            .with_options(with_keys(KEY_A, &[]));
+
+        ctrl.reencrypt_token().await.unwrap();
+        let encrypted = stored(&ctrl);
+        assert!(crypto::is_encrypted(&encrypted));
+        assert_eq!(ctrl.load_token().await.unwrap(), Some("token".into()));
+        assert_eq!(stored(&ctrl), encrypted);
+
+        let ctrl = ctrl.with_options(with_keys(KEY_B, &[KEY_A]));
+        ctrl.reencrypt_token().await.unwrap();
+        assert_ne!(stored(&ctrl), encrypted);
+        let ctrl = ctrl.with_options(with_keys(KEY_B, &[]));
+        assert_eq!(ctrl.load_token().await.unwrap(), Some("token".into()));
+
+        let ctrl = ctrl.with_options(Options::default());
+        assert!(matches!(
+            ctrl.load_token().await,
+            Err(Error::NoEncryptionKey)
+        ));
+
+        let fake = FakeSpotify::new("me");
+        let authorized = controller(&fake, with_keys(KEY_A, &[]));
+        authorized.store_token().await.unwrap();
+        let stored = authorized.db.dump()[DBKEY_REFRESH_TOKEN].clone();
+        let cipher = crypto::Cipher::new(KEY_A, &[]).unwrap();
+        let decrypted = cipher.decrypt(&stored, DBKEY_REFRESH_TOKEN).unwrap();
+        assert_eq!(decrypted.plaintext, "fake");
+        assert!(!decrypted.stale);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_encrypted_token() {
+        const KEY_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        let cipher = crypto::Cipher::new(KEY_A, &[]).unwrap();
+        let db = Memory::from(std::collections::HashMap::from([(
+            DBKEY_REFRESH_TOKEN.to_string(),
+            cipher.encrypt("token", DBKEY_REFRESH_TOKEN),
+        )]));
+        let options = |namespace: &str| Options {
+            key_namespace: Some(namespace.into()),
+            migrate_unprefixed_keys: true,
+            token_cipher: Some(crypto::Cipher::new(KEY_A, &[]).unwrap()),
+            ..Default::default()
+        };
+        let ctrl = UnauthorizedController::new("id", "secret", "http://localhost".into(), db)
+            .with_options(options("prod"));
+
+        assert_eq!(ctrl.migrate_unprefixed_keys().await.unwrap(), 1);
+        assert_eq!(ctrl.load_token().await.unwrap(), Some("token".into()));
+
+        // The token is bound to its namespace, so a copy can't be read.
+        let stored = ctrl.db.dump()[&format!("prod:{DBKEY_REFRESH_TOKEN}")].clone();
+        ctrl.db
+            .set(format!("staging:{DBKEY_REFRESH_TOKEN}"), stored)
+            .await
+            .unwrap();
+        let ctrl = ctrl.with_options(options("staging"));
+        assert!(matches!(
+            ctrl.load_token().await,
+            Err(Error::TokenDecryptionFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_access_token() {
+        const KEY_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        const KEY_B: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBA=";
+        let cipher = crypto::Cipher::new(KEY_B, &[]).unwrap();
+        let with_value = |value: String| {
+            let db = Memory::from(std::collections::HashMap::from([(
+                DBKEY_ACCESS_TOKEN.to_string(),
+                value,
+            )]));
+            UnauthorizedController::new("id", "secret", "http://localhost".into(), db)
+        };
+
+        let ctrl = with_value("{not json".into());
+        assert!(ctrl.load_access_token().await.unwrap().is_none());
+        assert!(matches!(
+            ctrl.decode_access_token("{not json"),
+            Err(Error::InvalidAccessToken(_))
+        ));
+
+        let encrypted = cipher.encrypt("{}", DBKEY_ACCESS_TOKEN);
+        let ctrl = with_value(encrypted).with_options(Options {
+            token_cipher: Some(crypto::Cipher::new(KEY_A, &[]).unwrap()),
+            ..Default::default()
+        });
+        assert!(ctrl.load_access_token().await.unwrap().is_none());
+        let ctrl = ctrl.with_options(Options::default());
+        assert!(ctrl.load_access_token().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_adopt_existing_playlists() {
+        let fake = FakeSpotify::new("me");
+        fake.add_playlist("2000s", "someone", vec![]);
+        let existing = fake.add_playlist("2000s", "me", tracks(&["x"]));
+        fake.add_track("a", "2001");
+        fake.set_saved_tracks(&["a"]);
+        let options = Options {
+            adopt_existing_playlists: true,
+            ..Default::default()
+        };
+        let ctrl = controller(&fake, options);
+
+        let update = ctrl
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        assert_eq!(update.id, existing);
+        assert_eq!(fake.items(&existing), tracks(&["a"]));
+        let adopted = record(
+            &ctrl.db,
+            &format!("{DBKEY_PLAYLIST_TIMERANGE_PREFIX}:2000-2010"),
+        )
+        .await;
+        assert_eq!(adopted.created_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_items() {
+        let current = vec![
+            FakeItem::Track("a".into()),
+            FakeItem::Local("x".into()),
+            FakeItem::Episode("e".into()),
+            FakeItem::Track("b".into()),
+        ];
+
+        for (policy, expected) in [
+            (
+                ForeignItemsPolicy::MoveToEnd,
+                vec![
+                    FakeItem::Track("b".into()),
+                    FakeItem::Track("c".into()),
+                    FakeItem::Local("x".into()),
+                    FakeItem::Episode("e".into()),
+                ],
+            ),
+            (ForeignItemsPolicy::Remove, tracks(&["b", "c"])),
+        ] {
+            let fake = FakeSpotify::new("me");
+            let id = fake.add_playlist("p", "me", current.clone());
+            let options = Options {
+                foreign_items: policy,
+                ..Default::default()
+            };
+            let ctrl = controller(&fake, options);
+
+            let outcome = ctrl
+                .update_playlist(id.clone(), playable(&["b", "c"]))
+                .await;
+            assert_eq!(outcome.unwrap(), UpdateOutcome::Updated);
+            assert_eq!(fake.items(&id), expected, "{policy:?}");
+        }
+
+        let fake = FakeSpotify::new("me");
+        let id = fake.add_playlist("p", "me", current.clone());
+        let ctrl = controller(&fake, Options::default());
+        let outcome = ctrl
+            .update_playlist(id.clone(), playable(&["a", "b"]))
+            .await;
+        assert_eq!(outcome.unwrap(), UpdateOutcome::Unchanged);
+        assert_eq!(fake.items(&id), current);
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        let fake = FakeSpotify::new("me");
+        let id = fake.add_playlist("p", "me", tracks(&["a", "b", "c"]));
+        let ctrl = controller(&fake, Options::default());
+
+        // The removal of a and b succeeds, the insertion of d fails.
+        fake.fail_write(1);
+        let res = ctrl
+            .update_playlist(id.clone(), playable(&["c", "d"]))
+            .await;
+        assert!(matches!(res, Err(Error::PlaylistUpdateRolledBack(_))));
+        assert_eq!(fake.items(&id), tracks(&["a", "b", "c"]));
+
+        fake.fail_write(1);
+        fake.fail_write(2);
+        let res = ctrl
+            .update_playlist(id.clone(), playable(&["c", "d"]))
+            .await;
+        assert!(matches!(res, Err(Error::PlaylistPartiallyUpdated { .. })));
+        assert_eq!(fake.items(&id), tracks(&["c"]));
+
+        // Removing the local file replaces all items in two writes.
+        let current = vec![FakeItem::Local("x".into()), FakeItem::Track("a".into())];
+        let desired: Vec<_> = (0..150).map(|i| format!("t{i}")).collect();
+        let desired: Vec<_> = desired.iter().map(String::as_str).collect();
+        let options = Options {
+            foreign_items: ForeignItemsPolicy::Remove,
+            ..Default::default()
+        };
+
+        let id = fake.add_playlist("q", "me", current.clone());
+        let ctrl = controller(&fake, options.clone());
+        fake.fail_write(0);
+        let res = ctrl.update_playlist(id.clone(), playable(&desired)).await;
+        assert!(matches!(res, Err(Error::PlaylistUpdateRolledBack(_))));
+        assert_eq!(fake.items(&id), current);
+
+        fake.fail_write(1);
+        let res = ctrl.update_playlist(id.clone(), playable(&desired)).await;
+        assert!(
+            matches!(&res, Err(Error::PlaylistLocalFilesLost { lost, .. }) if *lost == ["x"]),
+            "{res:?}"
+        );
+        assert_eq!(fake.items(&id), tracks(&["a"]));
+    }
 
     #[test]
     fn test_year() {
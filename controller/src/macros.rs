@@ -0,0 +1,15 @@
+macro_rules! from_env {
+    ($name:literal) => {
+        std::env::var($name).map_err(|err| $crate::errors::Error::EnvVar { name: $name, err })
+    };
+}
+
+macro_rules! optional_from_env {
+    ($name:literal) => {
+        match std::env::var($name) {
+            Ok(v) => Ok(Some(v)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(err) => Err($crate::errors::Error::EnvVar { name: $name, err }),
+        }
+    };
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+use std::time::Duration;
+
+/// Something the user should know about, sent to the configured webhook.
+#[derive(Debug, Serialize)]
+pub(crate) struct Notification<'a> {
+    pub event: &'a str,
+    pub text: &'a str,
+}
+
+/// Sends the notification to the webhook. Failures are only logged, as they
+/// must not hide the error which is being reported.
+pub(crate) async fn send(url: &str, notification: &Notification<'_>) {
+    let res = reqwest::Client::new()
+        .post(url)
+        .timeout(Duration::from_secs(10))
+        .json(notification)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status());
+
+    if let Err(err) = res {
+        log::warn!(
+            "Sending the {} notification failed: {err}",
+            notification.event
+        );
+    }
+}
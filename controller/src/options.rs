@@ -0,0 +1,207 @@
+use crate::crypto::Cipher;
+use crate::errors::{Error, Result};
+use crate::ratelimit::RateLimiter;
+use crate::retry::Backoff;
+use std::str::FromStr;
+use std::time::Duration;
+
+macro_rules! flag_from_env {
+    ($name:literal) => {
+        optional_from_env!($name)?
+            .map(|v| parse_flag($name, v))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    };
+}
+
+macro_rules! parsed_from_env {
+    ($name:literal) => {
+        optional_from_env!($name)?
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| Error::InvalidEnvVar { name: $name, value })
+            })
+            .transpose()
+    };
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Create managed playlists anew when the stored playlist has been deleted
+    /// or unfollowed instead of failing with [`Error::PlaylistDoesNotExist`].
+    pub recreate_deleted_playlists: bool,
+
+    /// Reuse an existing playlist owned by the user with the target name
+    /// instead of creating a new one when no playlist ID has been stored.
+    pub adopt_existing_playlists: bool,
+
+    /// How local files and podcast episodes in managed playlists are handled.
+    pub foreign_items: ForeignItemsPolicy,
+
+    /// Number of requests which can be sent to Spotify at once before the
+    /// client side rate limit kicks in.
+    pub request_burst: u32,
+
+    /// Number of requests per second which can be sent to Spotify on average.
+    /// `0` disables the client side rate limit.
+    pub requests_per_second: f64,
+
+    /// Maximum number of retries of a request which failed with a transient
+    /// error like a 5xx response or a connection reset.
+    pub max_retries: u32,
+
+    /// Upper bound of the delay between two retries.
+    pub max_backoff: Duration,
+
+    /// Base URL of the Spotify Web API, e.g. to point the controller at a
+    /// local stand-in. Defaults to [`rspotify::DEFAULT_API_BASE_URL`].
+    pub api_base_url: Option<String>,
+
+    /// Base URL of the Spotify accounts service used for authorization.
+    /// Defaults to [`rspotify::DEFAULT_AUTH_BASE_URL`].
+    pub auth_base_url: Option<String>,
+
+    /// Prefix of all keys in the KV store, so multiple deployments can share
+    /// one store without overwriting each other's state.
+    pub key_namespace: Option<String>,
+
+    /// Move keys stored without a namespace into [`Options::key_namespace`]
+    /// once, e.g. after adding a namespace to an existing deployment.
+    pub migrate_unprefixed_keys: bool,
+
+    /// Encrypts the refresh token before storing it. Tokens stored in plain
+    /// text or with an old key are re-encrypted when they are read.
+    pub token_cipher: Option<Cipher>,
+
+    /// URL which is sent a JSON `POST` request when the app needs attention,
+    /// e.g. because the Spotify authorization has been revoked.
+    pub notify_webhook_url: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            recreate_deleted_playlists: false,
+            adopt_existing_playlists: false,
+            foreign_items: ForeignItemsPolicy::default(),
+            request_burst: 10,
+            requests_per_second: 5.0,
+            max_retries: 3,
+            max_backoff: Duration::from_secs(10),
+            api_base_url: None,
+            auth_base_url: None,
+            key_namespace: None,
+            migrate_unprefixed_keys: false,
+            token_cipher: None,
+            notify_webhook_url: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForeignItemsPolicy {
+    /// Leave the items at their current position.
+    #[default]
+    Keep,
+    /// Move the items behind all managed tracks.
+    MoveToEnd,
+    /// Remove the items from the playlist.
+    Remove,
+}
+
+impl FromStr for ForeignItemsPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "end" | "move_to_end" => Ok(Self::MoveToEnd),
+            "remove" => Ok(Self::Remove),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Options {
+    pub fn from_env() -> Result<Self> {
+        let defaults = Options::default();
+        Ok(Options {
+            recreate_deleted_playlists: flag_from_env!("RECREATE_DELETED_PLAYLISTS")?,
+            adopt_existing_playlists: flag_from_env!("ADOPT_EXISTING_PLAYLISTS")?,
+            foreign_items: parsed_from_env!("FOREIGN_ITEMS_POLICY")?
+                .unwrap_or(defaults.foreign_items),
+            request_burst: parsed_from_env!("SPOTIFY_REQUEST_BURST")?
+                .unwrap_or(defaults.request_burst),
+            requests_per_second: parsed_from_env!("SPOTIFY_REQUESTS_PER_SECOND")?
+                .unwrap_or(defaults.requests_per_second),
+            max_retries: parsed_from_env!("SPOTIFY_MAX_RETRIES")?.unwrap_or(defaults.max_retries),
+            max_backoff: parsed_from_env!("SPOTIFY_MAX_BACKOFF_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_backoff),
+            api_base_url: optional_from_env!("SPOTIFY_API_BASE_URL")?,
+            auth_base_url: optional_from_env!("SPOTIFY_ACCOUNTS_BASE_URL")?,
+            key_namespace: optional_from_env!("KV_NAMESPACE")?.filter(|ns| !ns.is_empty()),
+            migrate_unprefixed_keys: flag_from_env!("KV_MIGRATE_UNPREFIXED_KEYS")?,
+            token_cipher: cipher_from_env()?,
+            notify_webhook_url: optional_from_env!("NOTIFY_WEBHOOK_URL")?,
+        })
+    }
+
+    /// Returns the key in the KV store for the given key.
+    pub fn key(&self, key: &str) -> String {
+        match &self.key_namespace {
+            Some(ns) => format!("{ns}:{key}"),
+            None => key.to_string(),
+        }
+    }
+
+    pub(crate) fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.request_burst, self.requests_per_second)
+    }
+
+    pub(crate) fn backoff(&self) -> Backoff {
+        Backoff {
+            max_retries: self.max_retries,
+            base: Duration::from_millis(500),
+            max: self.max_backoff,
+        }
+    }
+}
+
+fn cipher_from_env() -> Result<Option<Cipher>> {
+    let Some(key) = optional_from_env!("TOKEN_ENCRYPTION_KEY")? else {
+        return Ok(None);
+    };
+    let old_keys = optional_from_env!("TOKEN_ENCRYPTION_OLD_KEYS")?.unwrap_or_default();
+    let old_keys: Vec<_> = old_keys
+        .split(',')
+        .filter(|k| !k.trim().is_empty())
+        .collect();
+    Cipher::new(&key, &old_keys).map(Some)
+}
+
+fn parse_flag(name: &'static str, value: String) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err(Error::InvalidEnvVar { name, value }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_flag() {
+        assert!(matches!(parse_flag("X", "true".into()), Ok(true)));
+        assert!(matches!(parse_flag("X", "ON".into()), Ok(true)));
+        assert!(matches!(parse_flag("X", "0".into()), Ok(false)));
+        assert!(matches!(parse_flag("X", "".into()), Ok(false)));
+        assert!(matches!(
+            parse_flag("X", "maybe".into()),
+            Err(Error::InvalidEnvVar { name: "X", .. })
+        ));
+    }
+}
@@ -0,0 +1,37 @@
+use rspotify::model::PlaylistId;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateOutcome {
+    /// The playlist contents have been changed.
+    Updated,
+    /// The playlist already had the desired contents, so nothing was written.
+    Unchanged,
+}
+
+impl Display for UpdateOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateOutcome::Updated => write!(f, "updated"),
+            UpdateOutcome::Unchanged => write!(f, "unchanged"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlaylistUpdate {
+    pub id: PlaylistId<'static>,
+    pub outcome: UpdateOutcome,
+    /// The snapshot of the playlist after the update, if it is known.
+    pub snapshot_id: Option<String>,
+    /// Number of tracks managed by the automation.
+    pub tracks: usize,
+}
+
+impl Display for PlaylistUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.id, self.outcome)
+    }
+}
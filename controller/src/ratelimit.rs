@@ -0,0 +1,114 @@
+use crate::errors::{Error, Result};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket limiting the rate of requests sent to the Spotify API.
+///
+/// The limiter is shared between all controllers created from the same
+/// [`UnauthorizedController`](crate::UnauthorizedController), so concurrently
+/// running automations share the same request budget.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+    burst: f64,
+    per_second: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a new limiter allowing `burst` requests at once which refills
+    /// with `per_second` requests per second. A `per_second` value of `0`
+    /// disables the client side limit.
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated: Instant::now(),
+                blocked_until: None,
+            }),
+            burst,
+            per_second,
+        }
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) -> Result<()> {
+        while let Some(wait) = self.try_acquire(Instant::now())? {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+
+    /// Blocks all requests for the given duration, for example after Spotify
+    /// responded with a `Retry-After` header.
+    pub fn block_for(&self, duration: Duration) -> Result<()> {
+        let mut bucket = self.bucket.lock().map_err(|_| Error::LockPoisoned)?;
+        let until = Instant::now() + duration;
+        if bucket.blocked_until.is_none_or(|v| v < until) {
+            bucket.blocked_until = Some(until);
+        }
+        Ok(())
+    }
+
+    fn try_acquire(&self, now: Instant) -> Result<Option<Duration>> {
+        let mut bucket = self.bucket.lock().map_err(|_| Error::LockPoisoned)?;
+
+        if let Some(until) = bucket.blocked_until {
+            if until > now {
+                return Ok(Some(until - now));
+            }
+            bucket.blocked_until = None;
+        }
+
+        if self.per_second <= 0.0 {
+            return Ok(None);
+        }
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(None);
+        }
+
+        let missing = 1.0 - bucket.tokens;
+        Ok(Some(Duration::from_secs_f64(missing / self.per_second)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_try_acquire() {
+        let limiter = RateLimiter::new(2, 2.0);
+        let now = Instant::now();
+
+        assert!(matches!(limiter.try_acquire(now), Ok(None)));
+        assert!(matches!(limiter.try_acquire(now), Ok(None)));
+        assert!(matches!(limiter.try_acquire(now), Ok(Some(d)) if d <= Duration::from_millis(500)));
+
+        let later = now + Duration::from_millis(500);
+        assert!(matches!(limiter.try_acquire(later), Ok(None)));
+    }
+
+    #[test]
+    fn test_block_for() {
+        let limiter = RateLimiter::new(10, 0.0);
+        assert!(matches!(limiter.try_acquire(Instant::now()), Ok(None)));
+
+        limiter.block_for(Duration::from_secs(5)).unwrap();
+        assert!(matches!(
+            limiter.try_acquire(Instant::now()),
+            Ok(Some(d)) if d > Duration::from_secs(4)
+        ));
+    }
+}
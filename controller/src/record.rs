@@ -0,0 +1,133 @@
+use crate::errors::{Error, Result};
+use crate::outcome::{PlaylistUpdate, UpdateOutcome};
+use chrono::{DateTime, Utc};
+use rspotify::model::PlaylistId;
+use serde::{Deserialize, Serialize};
+
+/// Version of the records written by this version of the controller.
+pub const VERSION: u32 = 1;
+
+/// The automation which manages a playlist and its parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Automation {
+    MostPlayed {
+        name_prefix: String,
+        time_range: String,
+        limit: Option<usize>,
+    },
+    TimeRange {
+        from: u32,
+        to: u32,
+    },
+    Dwa {
+        source: String,
+    },
+}
+
+/// What is stored about a managed playlist.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistRecord {
+    pub version: u32,
+    pub automation: Automation,
+    pub name: String,
+    pub playlist_id: String,
+    /// Unknown for playlists created before records have been stored and for
+    /// existing playlists which have been adopted.
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub snapshot_id: Option<String>,
+    pub track_count: Option<usize>,
+    pub last_outcome: Option<UpdateOutcome>,
+    /// Set when the last run failed, in which case there is no outcome.
+    pub last_error: Option<String>,
+}
+
+impl PlaylistRecord {
+    pub(crate) fn new(automation: Automation, name: &str, id: &PlaylistId<'_>) -> Self {
+        PlaylistRecord {
+            version: VERSION,
+            automation,
+            name: name.to_string(),
+            playlist_id: id.to_string(),
+            created_at: Some(Utc::now()),
+            last_run_at: None,
+            snapshot_id: None,
+            track_count: None,
+            last_outcome: None,
+            last_error: None,
+        }
+    }
+
+    /// Parses a stored record. Before records were introduced, only the
+    /// playlist ID was stored, which is turned into a record with the given
+    /// automation and name.
+    pub(crate) fn parse(value: &str, automation: Automation, name: &str) -> Result<Self> {
+        if !value.trim_start().starts_with('{') {
+            let mut record =
+                PlaylistRecord::new(automation, name, &PlaylistId::from_id_or_uri(value)?);
+            record.created_at = None;
+            return Ok(record);
+        }
+
+        let record: PlaylistRecord = serde_json::from_str(value)?;
+        if record.version > VERSION {
+            return Err(Error::UnsupportedRecordVersion(record.version));
+        }
+        Ok(record)
+    }
+
+    pub(crate) fn id(&self) -> Result<PlaylistId<'static>> {
+        Ok(PlaylistId::from_id_or_uri(&self.playlist_id)?.clone_static())
+    }
+
+    /// Stores the result of a run of the automation.
+    pub(crate) fn record_run(&mut self, update: &PlaylistUpdate) {
+        self.version = VERSION;
+        self.last_run_at = Some(Utc::now());
+        self.snapshot_id = update.snapshot_id.clone();
+        self.track_count = Some(update.tracks);
+        self.last_outcome = Some(update.outcome);
+        self.last_error = None;
+    }
+
+    /// Stores that a run of the automation failed.
+    pub(crate) fn record_failure(&mut self, err: &Error) {
+        self.version = VERSION;
+        self.last_run_at = Some(Utc::now());
+        self.last_outcome = None;
+        self.last_error = Some(err.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let automation = Automation::TimeRange {
+            from: 2000,
+            to: 2010,
+        };
+        let id = "37i9dQZF1DXcBWIGoYBM5M";
+
+        let legacy = PlaylistRecord::parse(id, automation.clone(), "2000s").unwrap();
+        assert_eq!(legacy.playlist_id, format!("spotify:playlist:{id}"));
+        assert_eq!(legacy.automation, automation);
+        assert_eq!(legacy.created_at, None);
+
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert!(json.contains(r#""kind":"time_range""#), "{json}");
+        assert_eq!(
+            PlaylistRecord::parse(&json, automation.clone(), "other").unwrap(),
+            legacy
+        );
+
+        let future = json.replace(r#""version":1"#, r#""version":2"#);
+        assert!(matches!(
+            PlaylistRecord::parse(&future, automation, "2000s"),
+            Err(Error::UnsupportedRecordVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+use rand::Rng;
+use rspotify::http::HttpError;
+use rspotify::ClientError;
+use std::time::Duration;
+
+/// Describes whether a request may be sent again after it failed with a
+/// transient error without risking to apply it twice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Idempotency {
+    Idempotent,
+    NonIdempotent,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Spotify rejected the request because of the rate limit and asks to wait
+    /// for the given duration.
+    RateLimited(Duration),
+    /// The request failed because of a server or connection error and might
+    /// succeed when sent again.
+    Transient,
+    /// The request failed and will fail again when retried.
+    Fatal,
+}
+
+pub fn classify(err: &ClientError) -> ErrorClass {
+    let ClientError::Http(http_err) = err else {
+        return match err {
+            ClientError::Io(_) => ErrorClass::Transient,
+            _ => ErrorClass::Fatal,
+        };
+    };
+
+    match http_err.as_ref() {
+        HttpError::StatusCode(res) => match res.status().as_u16() {
+            429 => ErrorClass::RateLimited(Duration::from_secs(
+                res.headers()
+                    .get("retry-after")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse().ok())
+                    .unwrap_or(1),
+            )),
+            500 | 502 | 503 | 504 => ErrorClass::Transient,
+            _ => ErrorClass::Fatal,
+        },
+        HttpError::Client(err) if err.is_timeout() || err.is_connect() || err.is_request() => {
+            ErrorClass::Transient
+        }
+        HttpError::Client(_) => ErrorClass::Fatal,
+    }
+}
+
+pub fn status_code(err: &ClientError) -> Option<u16> {
+    match err {
+        ClientError::Http(err) => match err.as_ref() {
+            HttpError::StatusCode(res) => Some(res.status().as_u16()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Exponential backoff with jitter used to space out retries of requests
+/// which failed with a transient error.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub max_retries: u32,
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Returns the duration to wait before the given retry attempt, starting
+    /// at `0`. The delay is picked randomly between half and the full
+    /// exponential delay, so concurrent runs don't retry in lockstep.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max);
+        rand::thread_rng().gen_range(delay / 2..=delay)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let backoff = Backoff {
+            max_retries: 5,
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+        };
+
+        for (attempt, expected) in [
+            (0, 100),
+            (1, 200),
+            (2, 400),
+            (3, 800),
+            (4, 1000),
+            (30, 1000),
+            (u32::MAX, 1000),
+        ] {
+            let expected = Duration::from_millis(expected);
+            for _ in 0..100 {
+                let delay = backoff.delay(attempt);
+                assert!(
+                    delay >= expected / 2 && delay <= expected,
+                    "{attempt}: {delay:?}"
+                );
+            }
+        }
+    }
+
+    fn status(status: u16, retry_after: Option<&str>) -> ClientError {
+        let mut res = http::Response::builder().status(status);
+        if let Some(retry_after) = retry_after {
+            res = res.header("retry-after", retry_after);
+        }
+        let res = reqwest::Response::from(res.body("").unwrap());
+        ClientError::Http(Box::new(HttpError::StatusCode(res)))
+    }
+
+    #[test]
+    fn test_classify() {
+        let err = ClientError::Io(std::io::ErrorKind::ConnectionReset.into());
+        assert_eq!(classify(&err), ErrorClass::Transient);
+
+        let err = ClientError::InvalidToken;
+        assert_eq!(classify(&err), ErrorClass::Fatal);
+
+        for code in [500, 502, 503, 504] {
+            assert_eq!(classify(&status(code, None)), ErrorClass::Transient);
+        }
+        for code in [400, 401, 403, 404, 501] {
+            assert_eq!(classify(&status(code, None)), ErrorClass::Fatal);
+        }
+
+        assert_eq!(
+            classify(&status(429, Some("7"))),
+            ErrorClass::RateLimited(Duration::from_secs(7))
+        );
+        for retry_after in [None, Some("soon")] {
+            assert_eq!(
+                classify(&status(429, retry_after)),
+                ErrorClass::RateLimited(Duration::from_secs(1))
+            );
+        }
+        assert_eq!(status_code(&status(404, None)), Some(404));
+    }
+}
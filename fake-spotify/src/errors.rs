@@ -0,0 +1,56 @@
+use controller::errors::Error as ControllerError;
+use rocket::http::{Header, Status};
+use rocket::response::{self, Responder};
+use rocket::serde::json::{json, Json};
+use rocket::Request;
+use rspotify::http::HttpError;
+use rspotify::ClientError;
+
+/// An error response in the format of the Spotify Web API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+    pub retry_after: Option<u64>,
+}
+
+pub type Result<T> = std::result::Result<T, ApiError>;
+
+impl ApiError {
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = Status::from_code(self.status).unwrap_or(Status::InternalServerError);
+        let body = json!({ "error": { "status": self.status, "message": self.message } });
+
+        let mut response = (status, Json(body)).respond_to(request)?;
+        if let Some(retry_after) = self.retry_after {
+            response.set_header(Header::new("Retry-After", retry_after.to_string()));
+        }
+        Ok(response)
+    }
+}
+
+impl From<ControllerError> for ApiError {
+    fn from(err: ControllerError) -> Self {
+        let status = match &err {
+            ControllerError::SpotifyClient(ClientError::Http(http_err)) => {
+                match http_err.as_ref() {
+                    HttpError::StatusCode(res) => res.status().as_u16(),
+                    HttpError::Client(_) => 500,
+                }
+            }
+            _ => 500,
+        };
+
+        ApiError::new(status, err.to_string())
+    }
+}
@@ -0,0 +1,393 @@
+#[macro_use]
+extern crate rocket;
+
+mod errors;
+mod routes;
+
+use controller::fake::FakeSpotify;
+use rocket::serde::json::Value;
+use rocket::{Build, Rocket};
+use routes::{accounts, api, control};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The access token handed out by the fake accounts service.
+pub const ACCESS_TOKEN: &str = "fake-access-token";
+
+/// The authorization code the fake accounts service redirects back with.
+pub const AUTHORIZATION_CODE: &str = "fake-authorization-code";
+
+/// A response which is returned instead of the real one.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Failure {
+    pub status: u16,
+    /// Seconds sent in the `Retry-After` header.
+    pub retry_after: Option<u64>,
+}
+
+struct State {
+    refresh_token: String,
+    revoked: bool,
+    rotate_refresh_tokens: bool,
+    token_lifetime: u64,
+    token_requests: usize,
+    notifications: Vec<Value>,
+    failures: VecDeque<Failure>,
+}
+
+/// A stand-in for the Spotify Web API and accounts service.
+///
+/// The API is served below `/v1/` and the accounts service at the root, so
+/// the controller can be pointed at it with the `SPOTIFY_API_BASE_URL` and
+/// `SPOTIFY_ACCOUNTS_BASE_URL` environment variables. The state can be
+/// seeded and inspected through the [`FakeSpotify`] or the `/fake/` routes.
+#[derive(Clone)]
+pub struct FakeServer {
+    spotify: FakeSpotify,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeServer {
+    /// Creates a server which accepts the given refresh token.
+    pub fn new(spotify: FakeSpotify, refresh_token: &str) -> FakeServer {
+        FakeServer {
+            spotify,
+            state: Arc::new(Mutex::new(State {
+                refresh_token: refresh_token.to_string(),
+                revoked: false,
+                rotate_refresh_tokens: false,
+                token_lifetime: 3600,
+                token_requests: 0,
+                notifications: Vec::new(),
+                failures: VecDeque::new(),
+            })),
+        }
+    }
+
+    pub fn spotify(&self) -> &FakeSpotify {
+        &self.spotify
+    }
+
+    /// Makes the next `times` API requests fail with the given response.
+    pub fn fail(&self, failure: Failure, times: usize) {
+        let mut state = self.state();
+        state.failures.extend(std::iter::repeat_n(failure, times));
+    }
+
+    /// Revokes the refresh token until the user authorizes the app again.
+    pub fn revoke(&self) {
+        self.state().revoked = true;
+    }
+
+    /// Makes every refresh hand out a new refresh token, which invalidates
+    /// the previous one.
+    pub fn rotate_refresh_tokens(&self) {
+        self.state().rotate_refresh_tokens = true;
+    }
+
+    /// Sets the lifetime of the access tokens handed out, in seconds.
+    pub fn set_token_lifetime(&self, seconds: u64) {
+        self.state().token_lifetime = seconds;
+    }
+
+    /// Returns the number of requests sent to the token endpoint.
+    pub fn token_requests(&self) -> usize {
+        self.state().token_requests
+    }
+
+    /// Returns the notifications received at `/fake/notifications`.
+    pub fn notifications(&self) -> Vec<Value> {
+        self.state().notifications.clone()
+    }
+
+    fn notify(&self, notification: Value) {
+        self.state().notifications.push(notification);
+    }
+
+    /// Counts a request to the token endpoint and returns the lifetime of the
+    /// access token to hand out.
+    fn token_requested(&self) -> u64 {
+        let mut state = self.state();
+        state.token_requests += 1;
+        state.token_lifetime
+    }
+
+    /// Returns the refresh token to hand out after a successful refresh.
+    fn refresh(&self) -> Option<String> {
+        let mut state = self.state();
+        if !state.rotate_refresh_tokens {
+            return None;
+        }
+        state.refresh_token = format!("refresh-token-{}", state.token_requests);
+        Some(state.refresh_token.clone())
+    }
+
+    fn authorize(&self) -> String {
+        let mut state = self.state();
+        state.revoked = false;
+        state.refresh_token.clone()
+    }
+
+    fn is_valid(&self, refresh_token: &str) -> bool {
+        let state = self.state();
+        !state.revoked && state.refresh_token == refresh_token
+    }
+
+    fn next_failure(&self) -> Option<Failure> {
+        self.state().failures.pop_front()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn rocket(self) -> Rocket<Build> {
+        self.rocket_with(rocket::Config::figment())
+    }
+
+    fn rocket_with(self, figment: rocket::figment::Figment) -> Rocket<Build> {
+        rocket::custom(figment)
+            .manage(self)
+            .mount("/", accounts::routes())
+            .mount("/v1", api::routes())
+            .mount("/fake", control::routes())
+    }
+
+    /// Launches the server on a random local port in the background and
+    /// returns its base URL once it accepts requests.
+    pub async fn spawn(self) -> Option<String> {
+        let figment = rocket::Config::figment()
+            .merge(("address", "127.0.0.1"))
+            .merge(("port", 0))
+            .merge(("log_level", "off"))
+            .merge(("shutdown.ctrlc", false));
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let rocket = self
+            .rocket_with(figment)
+            .attach(rocket::fairing::AdHoc::on_liftoff("Port", |rocket| {
+                Box::pin(async move {
+                    let _ = tx.send(rocket.config().port);
+                })
+            }));
+
+        tokio::spawn(async move {
+            if let Err(err) = rocket.launch().await {
+                error!("fake Spotify server failed: {err}");
+            }
+        });
+
+        let port = rx.await.ok()?;
+        Some(format!("http://127.0.0.1:{port}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use controller::errors::Error;
+    use controller::fake::FakeItem;
+    use controller::options::Options;
+    use controller::{AuthorizedController, UnauthorizedController, UpdateOutcome};
+    use persistence::memory::Memory;
+    use rspotify::model::TimeRange;
+    use std::time::Duration;
+
+    const REFRESH_TOKEN: &str = "refresh-token";
+
+    async fn spawn() -> (FakeServer, UnauthorizedController<Memory>) {
+        let server = FakeServer::new(FakeSpotify::new("me"), REFRESH_TOKEN);
+        let url = server.clone().spawn().await.unwrap();
+
+        let options = Options {
+            api_base_url: Some(format!("{url}/v1/")),
+            auth_base_url: Some(format!("{url}/")),
+            notify_webhook_url: Some(format!("{url}/fake/notifications")),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let controller = UnauthorizedController::new(
+            "id",
+            "secret",
+            "http://localhost/callback".into(),
+            Memory::new(),
+        )
+        .with_options(options);
+
+        (server, controller)
+    }
+
+    async fn authorized(
+        controller: &UnauthorizedController<Memory>,
+    ) -> AuthorizedController<Memory> {
+        controller
+            .authorize_with_token(REFRESH_TOKEN.into())
+            .await
+            .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn test_authorization() {
+        let (server, controller) = spawn().await;
+
+        let url = controller.get_authorize_url().unwrap();
+        assert!(url.starts_with("http://127.0.0.1:"), "{url}");
+
+        let authorized = controller
+            .authorize_with_code(AUTHORIZATION_CODE)
+            .await
+            .unwrap();
+        assert_eq!(authorized.refresh_token().await.unwrap(), REFRESH_TOKEN);
+        assert!(controller.authorize_with_code("invalid").await.is_err());
+        assert!(controller
+            .authorize_with_token("invalid".into())
+            .await
+            .is_err());
+
+        server.revoke();
+        assert!(controller
+            .authorize_with_token(REFRESH_TOKEN.into())
+            .await
+            .is_err());
+    }
+
+    #[rocket::async_test]
+    async fn test_token_refresh() {
+        let (server, controller) = spawn().await;
+        // Too short-lived to be cached.
+        server.set_token_lifetime(60);
+        server.rotate_refresh_tokens();
+        controller
+            .authorize_with_code(AUTHORIZATION_CODE)
+            .await
+            .unwrap()
+            .store_token()
+            .await
+            .unwrap();
+
+        // Each refresh invalidates the previous refresh token, so this only
+        // works if the rotated one has been stored.
+        for _ in 0..2 {
+            let authorized = controller.authorize_from_db().await.unwrap();
+            assert!(authorized.get_top_songs(None, None).await.is_ok());
+        }
+        assert_eq!(server.token_requests(), 3);
+
+        server.set_token_lifetime(3600);
+        controller.authorize_from_db().await.unwrap();
+        for _ in 0..2 {
+            let authorized = controller.authorize_from_db().await.unwrap();
+            assert!(authorized.get_top_songs(None, None).await.is_ok());
+        }
+        assert_eq!(server.token_requests(), 4);
+    }
+
+    #[rocket::async_test]
+    async fn test_revoked_token() {
+        let (server, controller) = spawn().await;
+        server.set_token_lifetime(60);
+        let login = || async {
+            controller
+                .authorize_with_code(AUTHORIZATION_CODE)
+                .await
+                .unwrap()
+                .store_token()
+                .await
+                .unwrap();
+        };
+        login().await;
+
+        server.revoke();
+        for _ in 0..2 {
+            assert!(matches!(
+                controller.authorize_from_db().await,
+                Err(Error::TokenRevoked)
+            ));
+        }
+        assert_eq!(server.token_requests(), 2);
+        let notifications = server.notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0]["event"], "token_revoked");
+
+        login().await;
+        assert!(controller.authorize_from_db().await.is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn test_automations() {
+        let (server, controller) = spawn().await;
+        let spotify = server.spotify();
+        spotify.set_top_tracks(TimeRange::ShortTerm, &["a", "b", "c"]);
+        spotify.add_track("a", "2004-05-06");
+        spotify.add_track("b", "1999");
+        spotify.set_saved_tracks(&["a", "b"]);
+        spotify.add_playlist(
+            "Discover Weekly",
+            "spotify",
+            vec![FakeItem::Track("d".into())],
+        );
+        let controller = authorized(&controller).await;
+
+        let updates = controller
+            .update_mostplayed_playlists(["short"].iter(), "Top", None)
+            .await
+            .unwrap();
+        assert_eq!(updates[0].outcome, UpdateOutcome::Updated);
+        assert_eq!(spotify.items(&updates[0].id).len(), 3);
+
+        let update = controller
+            .update_timerange_playlist(2000..2010, "2000s")
+            .await
+            .unwrap();
+        assert_eq!(spotify.items(&update.id), vec![FakeItem::Track("a".into())]);
+
+        let update = controller
+            .update_dwa_playlist("Discover Weekly", "Archive")
+            .await
+            .unwrap();
+        assert_eq!(spotify.items(&update.id), vec![FakeItem::Track("d".into())]);
+    }
+
+    #[rocket::async_test]
+    async fn test_scripted_failures() {
+        let (server, controller) = spawn().await;
+        server
+            .spotify()
+            .set_top_tracks(TimeRange::MediumTerm, &["a", "b"]);
+        let controller = authorized(&controller).await;
+
+        let unavailable = Failure {
+            status: 503,
+            retry_after: None,
+        };
+        server.fail(unavailable, 2);
+        let songs = controller.get_top_songs(None, None).await.unwrap();
+        assert_eq!(songs.len(), 2);
+
+        let rate_limited = Failure {
+            status: 429,
+            retry_after: Some(0),
+        };
+        server.fail(rate_limited, 1);
+        assert!(controller.get_top_songs(None, None).await.is_ok());
+
+        let rate_limited = Failure {
+            status: 429,
+            retry_after: Some(3600),
+        };
+        server.fail(rate_limited, 1);
+        assert!(matches!(
+            controller.get_top_songs(None, None).await,
+            Err(Error::RateLimited(_))
+        ));
+
+        let not_found = Failure {
+            status: 404,
+            retry_after: None,
+        };
+        server.fail(not_found, 1);
+        assert!(controller.get_top_songs(None, None).await.is_err());
+        assert!(controller.get_top_songs(None, None).await.is_ok());
+    }
+}
@@ -0,0 +1,11 @@
+use controller::fake::FakeSpotify;
+use fake_spotify::FakeServer;
+
+#[rocket::launch]
+fn rocket() -> _ {
+    let user_id = std::env::var("FAKE_SPOTIFY_USER").unwrap_or_else(|_| "fake-user".into());
+    let refresh_token =
+        std::env::var("FAKE_SPOTIFY_REFRESH_TOKEN").unwrap_or_else(|_| "fake-refresh-token".into());
+
+    FakeServer::new(FakeSpotify::new(&user_id), &refresh_token).rocket()
+}
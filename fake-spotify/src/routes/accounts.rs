@@ -0,0 +1,63 @@
+use crate::{FakeServer, ACCESS_TOKEN, AUTHORIZATION_CODE};
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::response::Redirect;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{Route, State};
+
+/// Grants the authorization right away and redirects back to the app.
+#[get("/authorize?<redirect_uri>&<state>")]
+fn authorize(redirect_uri: &str, state: Option<&str>) -> Redirect {
+    let state = state.unwrap_or_default();
+    Redirect::to(format!(
+        "{redirect_uri}?code={AUTHORIZATION_CODE}&state={state}"
+    ))
+}
+
+#[derive(FromForm)]
+struct TokenRequest<'r> {
+    grant_type: &'r str,
+    code: Option<&'r str>,
+    refresh_token: Option<&'r str>,
+}
+
+#[post("/api/token", data = "<form>")]
+fn token(
+    server: &State<FakeServer>,
+    form: Form<TokenRequest<'_>>,
+) -> Result<Json<Value>, (Status, Json<Value>)> {
+    let mut token = json!({
+        "access_token": ACCESS_TOKEN,
+        "token_type": "Bearer",
+        "expires_in": server.token_requested(),
+        "scope": "user-top-read user-library-read playlist-read-private \
+                  playlist-modify-public playlist-modify-private",
+    });
+
+    match form.grant_type {
+        "authorization_code" if form.code == Some(AUTHORIZATION_CODE) => {
+            token["refresh_token"] = server.authorize().into();
+            Ok(Json(token))
+        }
+        "refresh_token" if form.refresh_token.is_some_and(|t| server.is_valid(t)) => {
+            if let Some(refresh_token) = server.refresh() {
+                token["refresh_token"] = refresh_token.into();
+            }
+            Ok(Json(token))
+        }
+        "authorization_code" => Err(error("invalid_grant", "Invalid authorization code")),
+        "refresh_token" => Err(error("invalid_grant", "Invalid refresh token")),
+        _ => Err(error("unsupported_grant_type", "Unsupported grant type")),
+    }
+}
+
+fn error(error: &str, description: &str) -> (Status, Json<Value>) {
+    (
+        Status::BadRequest,
+        Json(json!({ "error": error, "error_description": description })),
+    )
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![authorize, token]
+}
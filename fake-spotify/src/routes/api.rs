@@ -0,0 +1,294 @@
+use crate::errors::{ApiError, Result};
+use crate::{FakeServer, ACCESS_TOKEN};
+use controller::api::SpotifyApi;
+use controller::fake::FakeSpotify;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::{json, Json, Value};
+use rocket::{Request, Route};
+use rspotify::model::{
+    EpisodeId, FullPlaylist, FullTrack, Page, PlaylistId, PlaylistItem, PrivateUser, SavedTrack,
+    SimplifiedPlaylist, TimeRange, TrackId, UserId,
+};
+use rspotify::prelude::PlayableId;
+use serde::Deserialize;
+
+/// Grants access to the fake Spotify state to requests with a valid access
+/// token, unless a failure has been scripted for the request.
+pub struct Api<'r> {
+    server: &'r FakeServer,
+    authorized: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Api<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(server) = request.rocket().state::<FakeServer>() else {
+            return Outcome::Failure((Status::InternalServerError, ()));
+        };
+
+        let authorized = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            == Some(ACCESS_TOKEN);
+
+        Outcome::Success(Api { server, authorized })
+    }
+}
+
+impl Api<'_> {
+    fn spotify(&self) -> Result<&FakeSpotify> {
+        if !self.authorized {
+            return Err(ApiError::new(401, "Invalid access token"));
+        }
+
+        match self.server.next_failure() {
+            Some(failure) => Err(ApiError {
+                retry_after: failure.retry_after,
+                ..ApiError::new(failure.status, "Scripted failure")
+            }),
+            None => Ok(self.server.spotify()),
+        }
+    }
+}
+
+#[get("/me")]
+async fn me(api: Api<'_>) -> Result<Json<PrivateUser>> {
+    Ok(Json(api.spotify()?.current_user().await?))
+}
+
+#[get("/me/playlists?<limit>&<offset>")]
+async fn my_playlists(
+    api: Api<'_>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Json<Page<SimplifiedPlaylist>>> {
+    let page = api
+        .spotify()?
+        .current_user_playlists(limit.unwrap_or(20), offset.unwrap_or(0))
+        .await?;
+    Ok(Json(page))
+}
+
+#[get("/me/top/tracks?<time_range>&<limit>&<offset>")]
+async fn top_tracks(
+    api: Api<'_>,
+    time_range: Option<&str>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Json<Page<FullTrack>>> {
+    let time_range = match time_range {
+        None => None,
+        Some("short_term") => Some(TimeRange::ShortTerm),
+        Some("medium_term") => Some(TimeRange::MediumTerm),
+        Some("long_term") => Some(TimeRange::LongTerm),
+        Some(_) => return Err(ApiError::new(400, "Invalid time range")),
+    };
+
+    let page = api
+        .spotify()?
+        .current_user_top_tracks(time_range, limit.unwrap_or(20), offset.unwrap_or(0))
+        .await?;
+    Ok(Json(page))
+}
+
+#[get("/me/tracks?<limit>&<offset>")]
+async fn saved_tracks(
+    api: Api<'_>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<Json<Page<SavedTrack>>> {
+    let page = api
+        .spotify()?
+        .current_user_saved_tracks(limit.unwrap_or(20), offset.unwrap_or(0))
+        .await?;
+    Ok(Json(page))
+}
+
+#[get("/playlists/<id>")]
+async fn playlist(api: Api<'_>, id: &str) -> Result<Json<FullPlaylist>> {
+    Ok(Json(api.spotify()?.playlist(playlist_id(id)?).await?))
+}
+
+#[get("/playlists/<id>/tracks?<offset>")]
+async fn playlist_items(
+    api: Api<'_>,
+    id: &str,
+    offset: Option<u32>,
+) -> Result<Json<Page<PlaylistItem>>> {
+    let page = api
+        .spotify()?
+        .playlist_items(playlist_id(id)?, offset.unwrap_or(0))
+        .await?;
+    Ok(Json(page))
+}
+
+#[get("/playlists/<id>/followers/contains?<ids>")]
+async fn check_follow(api: Api<'_>, id: &str, ids: &str) -> Result<Json<Vec<bool>>> {
+    let spotify = api.spotify()?;
+    let mut follows = vec![];
+    for user_id in ids.split(',') {
+        let user_id =
+            UserId::from_id(user_id).map_err(|_| ApiError::new(400, "Invalid user ID"))?;
+        follows.push(
+            spotify
+                .playlist_check_follow(playlist_id(id)?, user_id)
+                .await?,
+        );
+    }
+    Ok(Json(follows))
+}
+
+#[derive(Deserialize)]
+struct NewPlaylist {
+    name: String,
+    description: Option<String>,
+}
+
+#[post("/users/<user_id>/playlists", data = "<body>")]
+async fn create_playlist(
+    api: Api<'_>,
+    user_id: &str,
+    body: Json<NewPlaylist>,
+) -> Result<(Status, Json<FullPlaylist>)> {
+    let user_id = UserId::from_id(user_id).map_err(|_| ApiError::new(400, "Invalid user ID"))?;
+    let playlist = api
+        .spotify()?
+        .create_playlist(user_id, &body.name, body.description.as_deref())
+        .await?;
+    Ok((Status::Created, Json(playlist)))
+}
+
+#[derive(Deserialize)]
+struct AddItems {
+    uris: Vec<String>,
+    position: Option<u32>,
+}
+
+#[post("/playlists/<id>/tracks", data = "<body>")]
+async fn add_items(api: Api<'_>, id: &str, body: Json<AddItems>) -> Result<(Status, Json<Value>)> {
+    let items = playable_ids(&body.uris)?;
+    let snapshot_id = api
+        .spotify()?
+        .playlist_add_items(playlist_id(id)?, &items, body.position)
+        .await?;
+    Ok((Status::Created, Json(json!({ "snapshot_id": snapshot_id }))))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UpdateItems {
+    Replace {
+        uris: Vec<String>,
+    },
+    Reorder {
+        range_start: usize,
+        insert_before: usize,
+        range_length: Option<usize>,
+        snapshot_id: Option<String>,
+    },
+}
+
+#[put("/playlists/<id>/tracks", data = "<body>")]
+async fn update_items(api: Api<'_>, id: &str, body: Json<UpdateItems>) -> Result<Json<Value>> {
+    let spotify = api.spotify()?;
+    let id = playlist_id(id)?;
+
+    match body.into_inner() {
+        UpdateItems::Replace { uris } => {
+            spotify
+                .playlist_replace_items(id.clone(), &playable_ids(&uris)?)
+                .await?;
+        }
+        UpdateItems::Reorder {
+            range_start,
+            insert_before,
+            range_length,
+            snapshot_id,
+        } => {
+            if range_length.unwrap_or(1) != 1 {
+                return Err(ApiError::new(400, "Only single items can be reordered"));
+            }
+            let snapshot_id = match snapshot_id {
+                Some(v) => v,
+                None => spotify.playlist(id.clone()).await?.snapshot_id,
+            };
+            spotify
+                .playlist_reorder_item(id.clone(), range_start, insert_before, &snapshot_id)
+                .await?;
+        }
+    }
+
+    let snapshot_id = spotify.playlist(id).await?.snapshot_id;
+    Ok(Json(json!({ "snapshot_id": snapshot_id })))
+}
+
+#[derive(Deserialize)]
+struct RemoveItems {
+    tracks: Vec<ItemPositions>,
+    snapshot_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ItemPositions {
+    uri: String,
+    positions: Vec<usize>,
+}
+
+#[delete("/playlists/<id>/tracks", data = "<body>")]
+async fn remove_items(api: Api<'_>, id: &str, body: Json<RemoveItems>) -> Result<Json<Value>> {
+    let spotify = api.spotify()?;
+    let id = playlist_id(id)?;
+
+    let mut items = vec![];
+    for track in &body.tracks {
+        let item = playable_id(&track.uri)?;
+        items.extend(track.positions.iter().map(|pos| (*pos, item.clone())));
+    }
+
+    let snapshot_id = match &body.snapshot_id {
+        Some(v) => v.clone(),
+        None => spotify.playlist(id.clone()).await?.snapshot_id,
+    };
+    let snapshot_id = spotify
+        .playlist_remove_items(id, &items, &snapshot_id)
+        .await?;
+    Ok(Json(json!({ "snapshot_id": snapshot_id })))
+}
+
+fn playlist_id(id: &str) -> Result<PlaylistId<'_>> {
+    PlaylistId::from_id(id).map_err(|_| ApiError::new(400, "Invalid playlist ID"))
+}
+
+fn playable_id(uri: &str) -> Result<PlayableId<'static>> {
+    if let Ok(id) = TrackId::from_uri(uri) {
+        return Ok(PlayableId::Track(id.into_static()));
+    }
+    match EpisodeId::from_uri(uri) {
+        Ok(id) => Ok(PlayableId::Episode(id.into_static())),
+        Err(_) => Err(ApiError::new(400, format!("Invalid URI: {uri}"))),
+    }
+}
+
+fn playable_ids(uris: &[String]) -> Result<Vec<PlayableId<'static>>> {
+    uris.iter().map(|uri| playable_id(uri)).collect()
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        me,
+        my_playlists,
+        top_tracks,
+        saved_tracks,
+        playlist,
+        playlist_items,
+        check_follow,
+        create_playlist,
+        add_items,
+        update_items,
+        remove_items
+    ]
+}
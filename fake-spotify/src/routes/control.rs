@@ -0,0 +1,132 @@
+use crate::{Failure, FakeServer};
+use controller::fake::FakeItem;
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{Route, State};
+use rspotify::model::{PlaylistId, TimeRange};
+use rspotify::prelude::Id;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ScriptedFailure {
+    #[serde(flatten)]
+    failure: Failure,
+    #[serde(default = "one")]
+    times: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// Makes the next API requests fail, e.g. with a 429 or 5xx response.
+#[post("/failures", data = "<body>")]
+fn failures(server: &State<FakeServer>, body: Json<ScriptedFailure>) -> Status {
+    server.fail(body.failure.clone(), body.times);
+    Status::NoContent
+}
+
+#[put("/top_tracks/<time_range>", data = "<ids>")]
+fn top_tracks(server: &State<FakeServer>, time_range: &str, ids: Json<Vec<String>>) -> Status {
+    let time_range = match time_range {
+        "short_term" => TimeRange::ShortTerm,
+        "medium_term" => TimeRange::MediumTerm,
+        "long_term" => TimeRange::LongTerm,
+        _ => return Status::BadRequest,
+    };
+
+    let ids: Vec<_> = ids.iter().map(String::as_str).collect();
+    server.spotify().set_top_tracks(time_range, &ids);
+    Status::NoContent
+}
+
+#[derive(Deserialize)]
+struct SavedTrack {
+    id: String,
+    release_date: Option<String>,
+}
+
+#[put("/saved_tracks", data = "<tracks>")]
+fn saved_tracks(server: &State<FakeServer>, tracks: Json<Vec<SavedTrack>>) -> Status {
+    let spotify = server.spotify();
+    for track in tracks.iter() {
+        if let Some(release_date) = &track.release_date {
+            spotify.add_track(&track.id, release_date);
+        }
+    }
+
+    let ids: Vec<_> = tracks.iter().map(|t| t.id.as_str()).collect();
+    spotify.set_saved_tracks(&ids);
+    Status::NoContent
+}
+
+#[derive(Deserialize)]
+struct NewPlaylist {
+    name: String,
+    owner: Option<String>,
+    #[serde(default)]
+    tracks: Vec<String>,
+}
+
+#[post("/playlists", data = "<playlist>")]
+fn create_playlist(server: &State<FakeServer>, playlist: Json<NewPlaylist>) -> Json<Value> {
+    let spotify = server.spotify();
+    let owner = playlist.owner.clone().unwrap_or_else(|| spotify.user_id());
+    let items = playlist
+        .tracks
+        .iter()
+        .cloned()
+        .map(FakeItem::Track)
+        .collect();
+
+    let id = spotify.add_playlist(&playlist.name, &owner, items);
+    Json(json!({ "id": id.id() }))
+}
+
+#[get("/playlists/<id>")]
+fn playlist_items(server: &State<FakeServer>, id: &str) -> Option<Json<Vec<String>>> {
+    let id = PlaylistId::from_id(id).ok()?;
+    let items = server
+        .spotify()
+        .items(&id)
+        .into_iter()
+        .map(|item| match item {
+            FakeItem::Track(id) => format!("spotify:track:{id}"),
+            FakeItem::Episode(id) => format!("spotify:episode:{id}"),
+            FakeItem::Local(name) => format!("spotify:local:{name}"),
+        })
+        .collect();
+    Some(Json(items))
+}
+
+/// Revokes the refresh token, like a user removing the app from their account.
+#[post("/revoke")]
+fn revoke(server: &State<FakeServer>) -> Status {
+    server.revoke();
+    Status::NoContent
+}
+
+/// Receives the notifications sent by the app, so they can be inspected.
+#[post("/notifications", data = "<notification>")]
+fn notify(server: &State<FakeServer>, notification: Json<Value>) -> Status {
+    server.notify(notification.into_inner());
+    Status::NoContent
+}
+
+#[get("/notifications")]
+fn notifications(server: &State<FakeServer>) -> Json<Vec<Value>> {
+    Json(server.notifications())
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        failures,
+        top_tracks,
+        saved_tracks,
+        create_playlist,
+        playlist_items,
+        revoke,
+        notify,
+        notifications
+    ]
+}
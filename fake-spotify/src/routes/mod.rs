@@ -0,0 +1,5 @@
+#![allow(unused_imports, renamed_and_removed_lints)]
+
+pub mod accounts;
+pub mod api;
+pub mod control;
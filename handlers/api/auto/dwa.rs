@@ -0,0 +1,34 @@
+use controller::UnauthorizedController;
+use persistence::store::Store;
+use vercel_runtime::{http, run, Body, Error, Request, Response, StatusCode};
+use vercel_utils::{authorization_error, expect, get_query_param, update_error};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(handler).await
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let dw_name = expect!(get_query_param(&req, "dw_name")).unwrap_or("Discover Weekly".into());
+    let dwa_name =
+        expect!(get_query_param(&req, "dwa_name")).unwrap_or("Discover Weekly Archive".into());
+
+    let db = expect!(Store::from_env(true));
+    let controller = expect!(UnauthorizedController::from_env(db));
+
+    let controller = expect!(controller.authorize_from_db().await,
+        Err(err) => authorization_error(err));
+
+    let update = expect!(controller.update_dwa_playlist(dw_name, dwa_name).await,
+        Err(err) if matches!(err, controller::errors::Error::NoPlaylistFound) => http::not_found("discover weekly playlist not found"),
+        Err(err) => update_error(err));
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::Text(format!(
+            "{} playlist: {}\napi calls: {}",
+            update.outcome,
+            update.id,
+            controller.api_calls()
+        )))?)
+}
@@ -1,7 +1,9 @@
 use controller::UnauthorizedController;
-use persistence::redis::Redis;
-use vercel_runtime::{http, run, Body, Error, Request, Response, StatusCode};
-use vercel_utils::{expect, get_query_param, get_query_param_parsed};
+use persistence::store::Store;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+use vercel_utils::{
+    authorization_error, expect, get_query_param, get_query_param_parsed, update_error,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -13,25 +15,33 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
     let name = expect!(get_query_param(&req, "name"));
     let limit: Option<usize> = expect!(get_query_param_parsed(&req, "limit"));
 
-    let db = expect!(Redis::from_env(true));
+    let db = expect!(Store::from_env(true));
     let controller = expect!(UnauthorizedController::from_env(db));
 
     let controller = expect!(controller.authorize_from_db().await,
-        Err(err) if matches!(err, controller::errors::Error::NoAuthToken) => http::bad_request("no authorization token stored"),
-        Err(err) => http::internal_server_error(err.to_string()));
+        Err(err) => authorization_error(err));
 
     let time_ranges = time_ranges.split(',').map(str::trim);
-    let ids = expect!(
+    let updates = expect!(
         controller
             .update_mostplayed_playlists(
                 time_ranges,
                 name.as_deref().unwrap_or("Current Top Songs"),
                 limit,
             )
-            .await
+            .await,
+        Err(err) => update_error(err)
     );
 
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(Body::Text(format!("updated playlists: {}", ids.join(", "))))?)
+        .body(Body::Text(format!(
+            "processed playlists: {}\napi calls: {}",
+            updates
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            controller.api_calls()
+        )))?)
 }
@@ -1,7 +1,9 @@
 use controller::UnauthorizedController;
-use persistence::redis::Redis;
+use persistence::store::Store;
 use vercel_runtime::{http, run, Body, Error, Request, Response, StatusCode};
-use vercel_utils::{expect, get_query_param, get_query_param_parsed};
+use vercel_utils::{
+    authorization_error, expect, get_query_param, get_query_param_parsed, update_error,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -25,17 +27,22 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         return http::bad_request("value for 'from' must be smaller than 'to'");
     }
 
-    let db = expect!(Redis::from_env(true));
+    let db = expect!(Store::from_env(true));
     let controller = expect!(UnauthorizedController::from_env(db));
 
     let controller = expect!(controller.authorize_from_db().await,
-        Err(err) if matches!(err, controller::errors::Error::NoAuthToken) => http::bad_request("no authorization token stored"),
-        Err(err) => http::internal_server_error(err.to_string()));
+        Err(err) => authorization_error(err));
 
     let name = name.unwrap_or_else(|| format!("Songs from {from} to {to}"));
-    let id = expect!(controller.update_timerange_playlist(from..to, name).await);
+    let update = expect!(controller.update_timerange_playlist(from..to, name).await,
+        Err(err) => update_error(err));
 
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(Body::Text(format!("updated playlist: {id}")))?)
+        .body(Body::Text(format!(
+            "{} playlist: {}\napi calls: {}",
+            update.outcome,
+            update.id,
+            controller.api_calls()
+        )))?)
 }
@@ -1,5 +1,5 @@
 use controller::UnauthorizedController;
-use persistence::redis::Redis;
+use persistence::store::Store;
 use vercel_runtime::{http, run, Body, Error, Request, Response, StatusCode};
 use vercel_utils::{expect, get_query_param};
 
@@ -9,7 +9,7 @@ async fn main() -> Result<(), Error> {
 }
 
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let db = expect!(Redis::from_env(true));
+    let db = expect!(Store::from_env(true));
     let controller = expect!(UnauthorizedController::from_env(db));
 
     let code = expect!(
@@ -1,9 +1,13 @@
 use envconfig::{Envconfig, Error};
+use persistence::store::Backend;
 
 #[derive(Envconfig, Debug)]
 pub struct Config {
     #[envconfig(from = "SA_AUTH_TOKEN")]
     pub auth_token: Option<String>,
+
+    #[envconfig(from = "KV_BACKEND", default = "redis")]
+    pub kv_backend: Backend,
 }
 
 impl Config {
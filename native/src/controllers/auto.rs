@@ -23,11 +23,20 @@ async fn mostplayed(
     let time_ranges = time_ranges.split(',').map(str::trim);
     let name = name.as_deref().unwrap_or("Current Top Songs");
 
-    let ids = controller
+    let updates = controller
         .update_mostplayed_playlists(time_ranges, name, limit)
         .await?;
 
-    Ok((Status::Ok, format!("updated playlists: {}", ids.join(", "))))
+    let updates: Vec<_> = updates.iter().map(ToString::to_string).collect();
+
+    Ok((
+        Status::Ok,
+        format!(
+            "processed playlists: {}\napi calls: {}",
+            updates.join(", "),
+            controller.api_calls()
+        ),
+    ))
 }
 
 #[get("/timeranges?<name>&<from>&<to>")]
@@ -54,11 +63,49 @@ async fn timeranges(
 
     let name = name.unwrap_or_else(|| format!("Songs from {from} to {to}"));
 
-    let id = controller.update_timerange_playlist(from..to, name).await?;
+    let update = controller.update_timerange_playlist(from..to, name).await?;
+
+    Ok((
+        Status::Ok,
+        format!(
+            "{} playlist: {}\napi calls: {}",
+            update.outcome,
+            update.id,
+            controller.api_calls()
+        ),
+    ))
+}
+
+#[get("/dwa?<dw_name>&<dwa_name>")]
+async fn dwa(
+    token: AuthToken<'_>,
+    cfg: &State<Config>,
+    controller: AuthorizedController,
+    dw_name: Option<String>,
+    dwa_name: Option<String>,
+) -> Result<(Status, String)> {
+    if let Some(auth_token) = &cfg.auth_token {
+        if !matches!(token, AuthToken::Bearer(token) if token == auth_token) {
+            return Ok((Status::Unauthorized, "invalid auth token".into()));
+        }
+    }
+
+    let dw_name = dw_name.as_deref().unwrap_or("Discover Weekly");
+    let dwa_name = dwa_name.as_deref().unwrap_or("Discover Weekly Archive");
+
+    let update = controller.update_dwa_playlist(dw_name, dwa_name).await?;
 
-    Ok((Status::Ok, format!("updated playlist: {id}")))
+    Ok((
+        Status::Ok,
+        format!(
+            "{} playlist: {}\napi calls: {}",
+            update.outcome,
+            update.id,
+            controller.api_calls()
+        ),
+    ))
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![mostplayed, timeranges]
+    routes![mostplayed, timeranges, dwa]
 }
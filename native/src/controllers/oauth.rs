@@ -1,19 +1,19 @@
 use crate::errors::Result;
 use controller::UnauthorizedController;
-use persistence::redis::Redis;
+use persistence::store::Store;
 use rocket::http::Status;
 use rocket::response::Redirect;
 use rocket::{Route, State};
 
 #[get("/login")]
-async fn login(controller: &State<UnauthorizedController<Redis>>) -> Result<Redirect> {
+async fn login(controller: &State<UnauthorizedController<Store>>) -> Result<Redirect> {
     let auth_url = controller.get_authorize_url()?;
     Ok(Redirect::temporary(auth_url))
 }
 
 #[get("/callback?<code>")]
 async fn callback(
-    controller: &State<UnauthorizedController<Redis>>,
+    controller: &State<UnauthorizedController<Store>>,
     code: String,
 ) -> Result<(Status, &'static str)> {
     let controller = controller.authorize_with_code(&code).await?;
@@ -12,7 +12,13 @@ impl From<controller::errors::Error> for ErrorResponse {
             ControllerError::InvalidTimeRange
             | ControllerError::InvalidYear(_)
             | ControllerError::NoAuthToken => Status::BadRequest,
-            ControllerError::AuthorizationFailed(_) => Status::Unauthorized,
+            ControllerError::AuthorizationFailed(_) | ControllerError::TokenRevoked => {
+                Status::Unauthorized
+            }
+            ControllerError::RateLimited(_) => Status::TooManyRequests,
+            ControllerError::NoPlaylistFound | ControllerError::PlaylistDoesNotExist => {
+                Status::NotFound
+            }
             _ => Status::InternalServerError,
         };
 
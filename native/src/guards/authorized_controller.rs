@@ -1,11 +1,12 @@
+use controller::errors::Error as ControllerError;
 use controller::UnauthorizedController;
-use persistence::redis::Redis;
+use persistence::store::Store;
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 use rocket::{Request, State};
 use std::ops::Deref;
 
-pub struct AuthorizedController(controller::AuthorizedController<Redis>);
+pub struct AuthorizedController(controller::AuthorizedController<Store>);
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AuthorizedController {
@@ -13,7 +14,7 @@ impl<'r> FromRequest<'r> for AuthorizedController {
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let res = request
-            .guard::<&State<UnauthorizedController<Redis>>>()
+            .guard::<&State<UnauthorizedController<Store>>>()
             .await;
 
         let controller = match res {
@@ -22,16 +23,30 @@ impl<'r> FromRequest<'r> for AuthorizedController {
             Outcome::Forward(fw) => return Outcome::Forward(fw),
         };
 
-        let Ok(authorized_controller) = controller.authorize_from_db().await else {
-            return Outcome::Failure((Status::Unauthorized, ()));
-        };
-
-        Outcome::Success(Self(authorized_controller))
+        match controller.authorize_from_db().await {
+            Ok(authorized_controller) => Outcome::Success(Self(authorized_controller)),
+            Err(ControllerError::TokenRevoked) => {
+                request.local_cache(|| Some(REAUTHORIZE));
+                Outcome::Failure((Status::Unauthorized, ()))
+            }
+            Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+        }
     }
 }
 
+const REAUTHORIZE: &str =
+    "spotify authorization has been revoked, authorize the app again at /oauth/login";
+
+/// Tells why the guard failed if the authorization has been revoked.
+#[catch(401)]
+pub fn unauthorized(request: &Request) -> &'static str {
+    request
+        .local_cache(|| None::<&'static str>)
+        .unwrap_or("unauthorized")
+}
+
 impl Deref for AuthorizedController {
-    type Target = controller::AuthorizedController<Redis>;
+    type Target = controller::AuthorizedController<Store>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use controller::options::Options;
+use controller::{DBKEY_ACCESS_TOKEN, DBKEY_PREFIX};
+use persistence::store::{Backend, Store};
+use persistence::transfer::{self, Change, Dump};
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+#[derive(Subcommand, Debug)]
+pub enum KvCommand {
+    /// Writes all stored state to a JSON file.
+    Export {
+        /// The file to write to, `-` for stdout.
+        file: PathBuf,
+        /// The backend to read from instead of `KV_BACKEND`.
+        #[arg(long)]
+        backend: Option<Backend>,
+    },
+    /// Writes the state from a file created by `export`.
+    Import {
+        /// The file to read from, `-` for stdin.
+        file: PathBuf,
+        /// The backend to write to instead of `KV_BACKEND`.
+        #[arg(long)]
+        backend: Option<Backend>,
+        /// Only show which keys would be created or overwritten.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Copies all stored state from one backend into another.
+    Migrate {
+        #[arg(long)]
+        from: Backend,
+        #[arg(long)]
+        to: Backend,
+        /// Only show which keys would be created or overwritten.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl KvCommand {
+    pub async fn run(self, default_backend: Backend) -> Result<()> {
+        // Keys are exported relative to the namespace, so the state can be
+        // moved into another namespace as well.
+        let namespace = Options::from_env()?.key("");
+
+        match self {
+            KvCommand::Export { file, backend } => {
+                let db = Store::open(backend.unwrap_or(default_backend), false)?;
+                let dump = export(&db, &namespace).await?;
+                let json = serde_json::to_string_pretty(&dump)? + "\n";
+
+                if file.as_os_str() == "-" {
+                    print!("{json}");
+                } else {
+                    fs::write(&file, json)
+                        .with_context(|| format!("failed writing {}", file.display()))?;
+                }
+                eprintln!("exported {} keys", dump.entries.len());
+            }
+            KvCommand::Import {
+                file,
+                backend,
+                dry_run,
+            } => {
+                let json = if file.as_os_str() == "-" {
+                    let mut json = String::new();
+                    io::stdin().read_to_string(&mut json)?;
+                    json
+                } else {
+                    fs::read_to_string(&file)
+                        .with_context(|| format!("failed reading {}", file.display()))?
+                };
+                let dump: Dump = serde_json::from_str(&json).context("invalid dump")?;
+
+                let db = Store::open(backend.unwrap_or(default_backend), false)?;
+                import(&db, &namespace, &dump, dry_run).await?;
+            }
+            KvCommand::Migrate { from, to, dry_run } => {
+                let source = Store::open(from, false)?;
+                let dump = export(&source, &namespace).await?;
+
+                let target = Store::open(to, false)?;
+                import(&target, &namespace, &dump, dry_run).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn export(db: &Store, namespace: &str) -> Result<Dump> {
+    let mut dump = transfer::export(db, namespace, DBKEY_PREFIX).await?;
+    // Imported values don't expire, so the cached access token would outlive
+    // its validity.
+    dump.entries.remove(DBKEY_ACCESS_TOKEN);
+    Ok(dump)
+}
+
+async fn import(db: &Store, namespace: &str, dump: &Dump, dry_run: bool) -> Result<()> {
+    let changes = match dry_run {
+        true => transfer::plan_import(db, namespace, dump).await?,
+        false => transfer::import(db, namespace, dump).await?,
+    };
+
+    // Values are never printed, as they contain the refresh token.
+    for (key, change) in &changes {
+        println!("{change:<9} {key}");
+    }
+
+    let count = |c| changes.iter().filter(|(_, change)| *change == c).count();
+    let summary = format!(
+        "{} created, {} overwritten, {} unchanged",
+        count(Change::Create),
+        count(Change::Overwrite),
+        count(Change::Unchanged)
+    );
+    match dry_run {
+        true => eprintln!("dry run, nothing written: {summary}"),
+        false => eprintln!("{summary}"),
+    }
+
+    Ok(())
+}
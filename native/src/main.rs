@@ -5,12 +5,32 @@ mod config;
 mod controllers;
 mod errors;
 mod guards;
+mod kv;
 
 use anyhow::Result;
+use clap::{Parser, Subcommand};
 use config::Config;
 use controller::UnauthorizedController;
 use controllers::{auto, oauth};
-use persistence::redis::Redis;
+use guards::authorized_controller;
+use kv::KvCommand;
+use persistence::store::Store;
+
+#[derive(Parser, Debug)]
+#[command(about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs the web server, which is the default.
+    Serve,
+    /// Exports, imports or migrates the stored state.
+    #[command(subcommand)]
+    Kv(KvCommand),
+}
 
 #[rocket::main]
 async fn main() -> Result<()> {
@@ -20,10 +40,15 @@ async fn main() -> Result<()> {
         .try_init()
         .expect("failed initializing logger");
 
+    let cli = Cli::parse();
     let cfg = Config::parse()?;
     debug!("Parsed config: {cfg:?}");
 
-    let db = Redis::from_env(false)?;
+    if let Some(Command::Kv(command)) = cli.command {
+        return command.run(cfg.kv_backend).await;
+    }
+
+    let db = Store::open(cfg.kv_backend, false)?;
     let controller = UnauthorizedController::from_env(db)?;
 
     rocket::build()
@@ -31,6 +56,7 @@ async fn main() -> Result<()> {
         .manage(cfg)
         .mount("/oauth", oauth::routes())
         .mount("/auto", auto::routes())
+        .register("/", catchers![authorized_controller::unauthorized])
         .launch()
         .await?;
 
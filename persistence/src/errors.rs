@@ -7,6 +7,30 @@ pub enum Error {
     #[error("redis error: {0}")]
     Redis(#[from] redis::RedisError),
 
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("background task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+
+    #[error("upstash error: {0}")]
+    Upstash(String),
+
+    #[error("dump version {0} is not supported")]
+    UnsupportedDump(u32),
+
+    #[error("invalid KV backend: {0}")]
+    InvalidBackend(String),
+
     #[error("env variable not found: {name}: {err}")]
     EnvVar { name: &'static str, err: VarError },
 }
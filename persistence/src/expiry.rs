@@ -0,0 +1,17 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returns the current time in milliseconds, as stored in `expires_at` by the
+/// SQLite and JSON file backends.
+pub(crate) fn now() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_millis() as u64
+}
+
+/// Returns when a value stored now with the given TTL expires. Capped at the
+/// largest integer SQLite can store.
+pub(crate) fn expires_at(ttl: Duration) -> u64 {
+    let ttl = ttl.as_millis().try_into().unwrap_or(u64::MAX);
+    now().saturating_add(ttl).min(i64::MAX as u64)
+}
@@ -0,0 +1,285 @@
+use super::errors::Result;
+use crate::expiry::{self, now};
+use crate::KV;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A stored value. Values without expiry are stored as plain strings, which
+/// keeps files written before values could expire readable.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Value(String),
+    Expiring { value: String, expires_at: u64 },
+}
+
+impl Entry {
+    /// Returns the value unless it has expired.
+    fn value(&self) -> Option<&str> {
+        match self {
+            Entry::Value(value) => Some(value),
+            Entry::Expiring { value, expires_at } if *expires_at > now() => Some(value),
+            Entry::Expiring { .. } => None,
+        }
+    }
+}
+
+type Map = BTreeMap<String, Entry>;
+
+/// Stores all keys in a single JSON file.
+///
+/// Writes go to a temporary file which then replaces the original, so the
+/// file is never left half written. A lock on a separate `.lock` file
+/// serializes access of multiple processes sharing the same file.
+#[derive(Clone)]
+pub struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonFile { path: path.into() }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let path = from_env!("KV_FILE_PATH")?;
+        Ok(JsonFile::new(path))
+    }
+
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(extension);
+        path.into()
+    }
+
+    fn lock(&self, exclusive: bool) -> Result<File> {
+        // The data file itself is replaced on every write, so the lock has to
+        // be held on a file which stays in place.
+        let file = private(OpenOptions::new().create(true).truncate(false).write(true))
+            .open(self.sibling(".lock"))?;
+        match exclusive {
+            true => file.lock()?,
+            false => file.lock_shared()?,
+        }
+        Ok(file)
+    }
+
+    fn read(&self) -> Result<Map> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Map::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Runs `f` on the stored values on the blocking thread pool.
+    async fn view<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Map) -> T + Send + 'static,
+    {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let _lock = db.lock(false)?;
+            Ok(f(&db.read()?))
+        })
+        .await?
+    }
+
+    /// Like [`JsonFile::view`], but the values are written back if `f`
+    /// changed them. Expired values are dropped on the way.
+    async fn update<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Map) -> T + Send + 'static,
+    {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let _lock = db.lock(true)?;
+            let before = db.read()?;
+            let mut map = before.clone();
+            map.retain(|_, entry| entry.value().is_some());
+
+            let res = f(&mut map);
+            if map != before {
+                db.write(&map)?;
+            }
+            Ok(res)
+        })
+        .await?
+    }
+
+    fn write(&self, map: &Map) -> Result<()> {
+        // The mode only applies to new files, so a file left behind by an
+        // interrupted write is removed first.
+        let tmp_path = self.sibling(".tmp");
+        match fs::remove_file(&tmp_path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        let mut tmp = private(OpenOptions::new().create_new(true).write(true)).open(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut tmp, map)?;
+        tmp.write_all(b"\n")?;
+        tmp.sync_all()?;
+
+        fs::rename(&tmp_path, &self.path)?;
+        sync_dir(&self.path)
+    }
+}
+
+/// Restricts new files to the owner, as the stored values include tokens.
+#[cfg(unix)]
+fn private(options: &mut OpenOptions) -> &mut OpenOptions {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600)
+}
+
+#[cfg(not(unix))]
+fn private(options: &mut OpenOptions) -> &mut OpenOptions {
+    options
+}
+
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    // Persist the rename itself, otherwise it might get lost on power loss.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+impl KV for JsonFile {
+    async fn set(&self, key: impl AsRef<str> + Send, val: impl AsRef<str> + Send) -> Result<()> {
+        let entry = Entry::Value(val.as_ref().to_string());
+        let key = key.as_ref().to_string();
+        self.update(move |map| map.insert(key, entry)).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: impl AsRef<str> + Send) -> Result<Option<String>> {
+        let key = key.as_ref().to_string();
+        self.view(move |map| map.get(&key)?.value().map(str::to_string))
+            .await
+    }
+
+    async fn delete(&self, key: impl AsRef<str> + Send) -> Result<()> {
+        let key = key.as_ref().to_string();
+        self.update(move |map| map.remove(&key)).await?;
+        Ok(())
+    }
+
+    async fn list_keys(&self, prefix: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        let prefix = prefix.as_ref().to_string();
+        self.view(move |map| {
+            map.iter()
+                .filter(|(key, entry)| key.starts_with(&prefix) && entry.value().is_some())
+                .map(|(key, _)| key.clone())
+                .collect()
+        })
+        .await
+    }
+
+    async fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> Result<()> {
+        let expires_at = expiry::expires_at(ttl);
+        let entry = Entry::Expiring {
+            value: val.as_ref().to_string(),
+            expires_at,
+        };
+        let key = key.as_ref().to_string();
+        self.update(move |map| map.insert(key, entry)).await?;
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        let (key, new) = (key.as_ref().to_string(), new.as_ref().to_string());
+        let old = old.map(str::to_string);
+        self.update(move |map| {
+            if map.get(&key).and_then(Entry::value) != old.as_deref() {
+                return false;
+            }
+            map.insert(key, Entry::Value(new));
+            true
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_json_file() {
+        let dir = env::temp_dir().join(format!("persistence-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kv.json");
+
+        let db = JsonFile::new(&path);
+        assert_eq!(db.get("a").await.unwrap(), None);
+        db.set("a", "1").await.unwrap();
+        assert_eq!(db.get("a").await.unwrap(), Some("1".into()));
+
+        // Every writer uses its own instance like separate processes would.
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    let db = JsonFile::new(path);
+                    for j in 0..10 {
+                        db.set(format!("{i}:{j}"), "v").await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let map: Map = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(map.len(), 81);
+        assert!(!db.sibling(".tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for path in [&path, &db.sibling(".lock")] {
+                let mode = fs::metadata(path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600, "{}", path.display());
+            }
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_json_file_operations() {
+        let dir = env::temp_dir().join(format!("persistence-test-ops-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        crate::testing::test_operations(&JsonFile::new(dir.join("kv.json"))).await;
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
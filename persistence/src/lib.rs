@@ -1,10 +1,60 @@
+#[macro_use]
+mod macros;
+
 pub mod errors;
+mod expiry;
+pub mod file;
+pub mod memory;
 pub mod noop;
 pub mod redis;
+pub mod sqlite;
+pub mod store;
+#[cfg(test)]
+mod testing;
+pub mod transfer;
+pub mod upstash;
 
 use errors::Result;
+use std::future::Future;
+use std::time::Duration;
+
+/// A key-value store. All operations are async, so backends doing I/O must
+/// not block the runtime while waiting for it.
+pub trait KV: Send + Sync {
+    fn set(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn get(
+        &self,
+        key: impl AsRef<str> + Send,
+    ) -> impl Future<Output = Result<Option<String>>> + Send;
+
+    /// Removes the key if it exists.
+    fn delete(&self, key: impl AsRef<str> + Send) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns all keys starting with `prefix` in ascending order.
+    fn list_keys(
+        &self,
+        prefix: impl AsRef<str> + Send,
+    ) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Like [`KV::set`], but the key is removed once `ttl` has passed.
+    fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<()>> + Send;
 
-pub trait KV {
-    fn set(&self, key: impl AsRef<str>, val: impl AsRef<str>) -> Result<()>;
-    fn get(&self, key: impl AsRef<str>) -> Result<Option<String>>;
+    /// Sets the key to `new` only if its current value is `old`, where `None`
+    /// means that the key does not exist. Returns whether the value was set.
+    fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> impl Future<Output = Result<bool>> + Send;
 }
@@ -0,0 +1,5 @@
+macro_rules! from_env {
+    ($name:literal) => {
+        std::env::var($name).map_err(|err| $crate::errors::Error::EnvVar { name: $name, err })
+    };
+}
@@ -0,0 +1,156 @@
+use crate::errors::Result;
+use crate::KV;
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+struct Entry {
+    val: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_live(&self) -> bool {
+        self.expires_at.is_none_or(|t| t > Instant::now())
+    }
+}
+
+/// Keeps all values in memory, so they are lost when the process exits.
+#[derive(Default)]
+pub struct Memory {
+    map: RwLock<HashMap<String, Entry>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of all stored keys and values.
+    pub fn dump(&self) -> HashMap<String, String> {
+        self.map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(_, entry)| entry.is_live())
+            .map(|(key, entry)| (key.clone(), entry.val.clone()))
+            .collect()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Entry>> {
+        self.map.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn insert(&self, key: &str, val: &str, expires_at: Option<Instant>) {
+        let entry = Entry {
+            val: val.to_string(),
+            expires_at,
+        };
+        self.write().insert(key.to_string(), entry);
+    }
+}
+
+impl From<HashMap<String, String>> for Memory {
+    fn from(map: HashMap<String, String>) -> Self {
+        let map = map
+            .into_iter()
+            .map(|(key, val)| {
+                let entry = Entry {
+                    val,
+                    expires_at: None,
+                };
+                (key, entry)
+            })
+            .collect();
+
+        Memory {
+            map: RwLock::new(map),
+        }
+    }
+}
+
+impl KV for Memory {
+    async fn set(&self, key: impl AsRef<str> + Send, val: impl AsRef<str> + Send) -> Result<()> {
+        self.insert(key.as_ref(), val.as_ref(), None);
+        Ok(())
+    }
+
+    async fn get(&self, key: impl AsRef<str> + Send) -> Result<Option<String>> {
+        let map = self.map.read().unwrap_or_else(PoisonError::into_inner);
+        let entry = map.get(key.as_ref()).filter(|entry| entry.is_live());
+        Ok(entry.map(|entry| entry.val.clone()))
+    }
+
+    async fn delete(&self, key: impl AsRef<str> + Send) -> Result<()> {
+        self.write().remove(key.as_ref());
+        Ok(())
+    }
+
+    async fn list_keys(&self, prefix: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        let map = self.map.read().unwrap_or_else(PoisonError::into_inner);
+        let mut keys: Vec<_> = map
+            .iter()
+            .filter(|(key, entry)| key.starts_with(prefix.as_ref()) && entry.is_live())
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.insert(key.as_ref(), val.as_ref(), Some(Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        let mut map = self.write();
+        let current = map.get(key.as_ref()).filter(|entry| entry.is_live());
+        if current.map(|entry| entry.val.as_str()) != old {
+            return Ok(false);
+        }
+
+        let entry = Entry {
+            val: new.as_ref().to_string(),
+            expires_at: None,
+        };
+        map.insert(key.as_ref().to_string(), entry);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory() {
+        let db = Memory::from(HashMap::from([("a".to_string(), "1".to_string())]));
+        assert_eq!(db.get("a").await.unwrap(), Some("1".into()));
+        assert_eq!(db.get("b").await.unwrap(), None);
+
+        db.set("b", "2").await.unwrap();
+        db.set("a", "3").await.unwrap();
+        assert_eq!(
+            db.dump(),
+            HashMap::from([
+                ("a".to_string(), "3".to_string()),
+                ("b".to_string(), "2".to_string())
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_operations() {
+        crate::testing::test_operations(&Memory::new()).await;
+    }
+}
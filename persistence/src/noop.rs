@@ -1,13 +1,42 @@
+use crate::errors::Result;
 use crate::KV;
+use std::time::Duration;
 
 pub struct NoOp;
 
 impl KV for NoOp {
-    fn set(&self, _: impl AsRef<str>, _: impl AsRef<str>) -> crate::errors::Result<()> {
+    async fn set(&self, _: impl AsRef<str> + Send, _: impl AsRef<str> + Send) -> Result<()> {
         Ok(())
     }
 
-    fn get(&self, _: impl AsRef<str>) -> crate::errors::Result<Option<String>> {
+    async fn get(&self, _: impl AsRef<str> + Send) -> Result<Option<String>> {
         Ok(None)
     }
+
+    async fn delete(&self, _: impl AsRef<str> + Send) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_keys(&self, _: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn set_with_ttl(
+        &self,
+        _: impl AsRef<str> + Send,
+        _: impl AsRef<str> + Send,
+        _: Duration,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        _: impl AsRef<str> + Send,
+        old: Option<&str>,
+        _: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        // Nothing is ever stored, so only a swap from a missing key succeeds.
+        Ok(old.is_none())
+    }
 }
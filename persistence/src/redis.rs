@@ -1,22 +1,58 @@
-use super::errors::{Error, Result};
+use super::errors::Result;
 use crate::KV;
-use redis::{Client, Commands};
-use std::env;
+use redis::aio::ConnectionManager;
+use redis::{Client, Cmd, FromRedisValue};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::OnceCell;
 
-macro_rules! from_env {
-    ($name:literal) => {
-        env::var($name).map_err(|err| Error::EnvVar { name: $name, err })
-    };
+/// Sets `KEYS[1]` to `ARGV[3]` if it exists (`ARGV[1]` is `1`) with the value
+/// `ARGV[2]`, or doesn't exist (`ARGV[1]` is `0`). Also used over REST.
+pub(crate) const COMPARE_AND_SWAP: &str = "\
+local current = redis.call('GET', KEYS[1])
+local expected = ARGV[1] == '1' and ARGV[2] or false
+if current ~= expected then return 0 end
+redis.call('SET', KEYS[1], ARGV[3])
+return 1";
+
+/// Returns a `MATCH` pattern for keys starting with `prefix`.
+pub(crate) fn prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::new();
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
 }
 
+type SharedConnection = Arc<OnceCell<ConnectionManager>>;
+
+/// Connections by URI, shared by all instances in the process so warm
+/// serverless invocations don't have to connect again.
+static CONNECTIONS: Mutex<BTreeMap<String, SharedConnection>> = Mutex::new(BTreeMap::new());
+
+/// Talks to Redis over a single multiplexed connection, which is opened on
+/// first use and reopened whenever it drops.
+#[derive(Clone)]
 pub struct Redis {
     client: Client,
+    conn: SharedConnection,
 }
 
 impl Redis {
     pub fn new(uri: &str) -> Result<Self> {
         let client = Client::open(uri)?;
-        Ok(Redis { client })
+        let conn = CONNECTIONS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(uri.to_string())
+            .or_default()
+            .clone();
+        Ok(Redis { client, conn })
     }
 
     pub fn from_env(ensure_tls: bool) -> Result<Self> {
@@ -27,16 +63,189 @@ impl Redis {
         }
         Redis::new(&uri)
     }
+
+    async fn conn(&self) -> Result<ConnectionManager> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await?;
+        Ok(conn.clone())
+    }
+
+    async fn query<T: FromRedisValue>(&self, cmd: &Cmd) -> Result<T> {
+        let mut conn = self.conn().await?;
+        match cmd.query_async(&mut conn).await {
+            // The failed command triggers a reconnect in the background, which
+            // the retry waits for.
+            Err(err) if err.is_connection_dropped() || err.is_io_error() => {
+                Ok(cmd.query_async(&mut conn).await?)
+            }
+            res => Ok(res?),
+        }
+    }
 }
 
 impl KV for Redis {
-    fn set(&self, key: impl AsRef<str>, val: impl AsRef<str>) -> Result<()> {
-        let mut conn = self.client.get_connection()?;
-        Ok(conn.set(key.as_ref(), val.as_ref())?)
+    async fn set(&self, key: impl AsRef<str> + Send, val: impl AsRef<str> + Send) -> Result<()> {
+        self.query(redis::cmd("SET").arg(key.as_ref()).arg(val.as_ref()))
+            .await
+    }
+
+    async fn get(&self, key: impl AsRef<str> + Send) -> Result<Option<String>> {
+        self.query(redis::cmd("GET").arg(key.as_ref())).await
+    }
+
+    async fn delete(&self, key: impl AsRef<str> + Send) -> Result<()> {
+        self.query(redis::cmd("DEL").arg(key.as_ref())).await
+    }
+
+    async fn list_keys(&self, prefix: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        let pattern = prefix_pattern(prefix.as_ref());
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let cmd = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(100)
+                .clone();
+            let (next, page): (u64, Vec<String>) = self.query(&cmd).await?;
+            keys.extend(page);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        // SCAN may return a key more than once.
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    async fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> Result<()> {
+        let ms = ttl.as_millis().max(1) as u64;
+        self.query(
+            redis::cmd("SET")
+                .arg(key.as_ref())
+                .arg(val.as_ref())
+                .arg("PX")
+                .arg(ms),
+        )
+        .await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        let cmd = redis::cmd("EVAL")
+            .arg(COMPARE_AND_SWAP)
+            .arg(1)
+            .arg(key.as_ref())
+            .arg(if old.is_some() { "1" } else { "0" })
+            .arg(old.unwrap_or_default())
+            .arg(new.as_ref())
+            .clone();
+        self.query(&cmd).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{Commands, Reply};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Serves Redis commands on a local port and counts the accepted
+    /// connections. Every connection is closed after `max_commands`.
+    fn serve(max_commands: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("redis://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        thread::spawn(move || {
+            let commands = Arc::new(Mutex::new(Commands::default()));
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let commands = commands.clone();
+
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let read_line = |reader: &mut BufReader<_>| {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        line.trim_end().to_string()
+                    };
+
+                    let mut handled = 0;
+                    while handled < max_commands {
+                        let Some(len) =
+                            read_line(&mut reader).strip_prefix('*').map(str::to_string)
+                        else {
+                            return;
+                        };
+                        // Every argument is sent as a length line and the value.
+                        let args: Vec<_> = (0..len.parse().unwrap())
+                            .map(|_| {
+                                let len: usize = read_line(&mut reader)[1..].parse().unwrap();
+                                let mut arg = vec![0; len + 2];
+                                reader.read_exact(&mut arg).unwrap();
+                                String::from_utf8_lossy(&arg[..len]).into_owned()
+                            })
+                            .collect();
+
+                        let args: Vec<_> = args.iter().map(String::as_str).collect();
+                        // Sent by the client when connecting.
+                        if args[0] == "CLIENT" {
+                            stream.write_all(Reply::Ok.to_resp().as_bytes()).unwrap();
+                            continue;
+                        }
+                        let reply = commands.lock().unwrap().execute(&args);
+                        stream.write_all(reply.to_resp().as_bytes()).unwrap();
+                        handled += 1;
+                    }
+                });
+            }
+        });
+
+        (uri, connections)
+    }
+
+    #[tokio::test]
+    async fn test_redis() {
+        let (uri, connections) = serve(3);
+
+        let db = Redis::new(&uri).unwrap();
+        assert_eq!(db.get("a").await.unwrap(), None);
+        db.set("a", "1").await.unwrap();
+
+        // Instances share the connection, so it is reused until the server
+        // closes it, and then reopened.
+        let db = Redis::new(&uri).unwrap();
+        assert_eq!(db.get("a").await.unwrap(), Some("1".into()));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(db.get("a").await.unwrap(), Some("1".into()));
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
     }
 
-    fn get(&self, key: impl AsRef<str>) -> Result<Option<String>> {
-        let mut conn = self.client.get_connection()?;
-        Ok(conn.get(key.as_ref())?)
+    #[tokio::test]
+    async fn test_redis_operations() {
+        let (uri, _) = serve(usize::MAX);
+        crate::testing::test_operations(&Redis::new(&uri).unwrap()).await;
     }
 }
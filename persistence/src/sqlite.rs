@@ -0,0 +1,186 @@
+use super::errors::Result;
+use crate::expiry::{self, now};
+use crate::KV;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS kv (
+    key        TEXT PRIMARY KEY NOT NULL,
+    value      TEXT NOT NULL,
+    expires_at INTEGER
+)";
+
+/// Matches rows which have not expired at the time given as `?1`.
+const LIVE: &str = "(expires_at IS NULL OR expires_at > ?1)";
+
+pub struct Sqlite {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Sqlite {
+    /// Opens the database at the given path, creating the file and the
+    /// schema if they don't exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Sqlite::init(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        Sqlite::init(Connection::open_in_memory()?)
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let path = from_env!("SQLITE_PATH")?;
+        Sqlite::open(path)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        // Wait for locks held by other processes using the same file, e.g.
+        // a running server and a cron job, instead of failing right away.
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute(SCHEMA, ())?;
+        // Databases created before values could expire lack the column.
+        if conn.prepare("SELECT expires_at FROM kv").is_err() {
+            conn.execute("ALTER TABLE kv ADD COLUMN expires_at INTEGER", ())?;
+        }
+        Ok(Sqlite {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` with the connection on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            f(&mut conn.lock().unwrap_or_else(PoisonError::into_inner))
+        })
+        .await?;
+        Ok(res?)
+    }
+}
+
+impl KV for Sqlite {
+    async fn set(&self, key: impl AsRef<str> + Send, val: impl AsRef<str> + Send) -> Result<()> {
+        let (key, val) = (key.as_ref().to_string(), val.as_ref().to_string());
+        self.with_conn(move |conn| upsert(conn, &key, &val, None))
+            .await
+    }
+
+    async fn get(&self, key: impl AsRef<str> + Send) -> Result<Option<String>> {
+        let key = key.as_ref().to_string();
+        self.with_conn(move |conn| get(conn, &key)).await
+    }
+
+    async fn delete(&self, key: impl AsRef<str> + Send) -> Result<()> {
+        let key = key.as_ref().to_string();
+        self.with_conn(move |conn| conn.execute("DELETE FROM kv WHERE key = ?1", (key,)))
+            .await?;
+        Ok(())
+    }
+
+    async fn list_keys(&self, prefix: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        let prefix = prefix.as_ref().to_string();
+        self.with_conn(move |conn| {
+            // substr instead of LIKE, which would need `%` and `_` escaped.
+            let mut stmt = conn.prepare(&format!(
+                "SELECT key FROM kv WHERE substr(key, 1, length(?2)) = ?2 AND {LIVE}
+                 ORDER BY key"
+            ))?;
+            let keys = stmt.query_map((now(), prefix), |row| row.get(0))?;
+            keys.collect()
+        })
+        .await
+    }
+
+    async fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> Result<()> {
+        let (key, val) = (key.as_ref().to_string(), val.as_ref().to_string());
+        let expires_at = expiry::expires_at(ttl);
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM kv WHERE expires_at <= ?1", (now(),))?;
+            upsert(conn, &key, &val, Some(expires_at))
+        })
+        .await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        let (key, new) = (key.as_ref().to_string(), new.as_ref().to_string());
+        let old = old.map(str::to_string);
+        self.with_conn(move |conn| {
+            // Take the write lock right away, so no other process can change
+            // the value between reading and writing it.
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            if get(&tx, &key)? != old {
+                return Ok(false);
+            }
+            upsert(&tx, &key, &new, None)?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+}
+
+fn get(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        &format!("SELECT value FROM kv WHERE key = ?2 AND {LIVE}"),
+        (now(), key),
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn upsert(
+    conn: &Connection,
+    key: &str,
+    val: &str,
+    expires_at: Option<u64>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO kv (key, value, expires_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value, expires_at = excluded.expires_at",
+        (key, val, expires_at),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[tokio::test]
+    async fn test_sqlite() {
+        let path = env::temp_dir().join(format!("persistence-test-{}.db", std::process::id()));
+
+        let db = Sqlite::open(&path).unwrap();
+        assert_eq!(db.get("a").await.unwrap(), None);
+        db.set("a", "1").await.unwrap();
+        db.set("a", "2").await.unwrap();
+        drop(db);
+
+        let db = Sqlite::open(&path).unwrap();
+        assert_eq!(db.get("a").await.unwrap(), Some("2".into()));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_operations() {
+        crate::testing::test_operations(&Sqlite::in_memory().unwrap()).await;
+    }
+}
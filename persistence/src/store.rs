@@ -0,0 +1,155 @@
+use crate::errors::{Error, Result};
+use crate::file::JsonFile;
+use crate::memory::Memory;
+use crate::redis::Redis;
+use crate::sqlite::Sqlite;
+use crate::upstash::Upstash;
+use crate::KV;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The available KV backends, which read their settings from the
+/// environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Connects to the Redis server at `KV_URL`.
+    Redis,
+    /// Uses the Upstash REST API at `KV_REST_API_URL` with the token
+    /// `KV_REST_API_TOKEN`.
+    Rest,
+    /// Keeps everything in memory, so all state is lost on restart.
+    Memory,
+    /// Stores everything in the SQLite database at `SQLITE_PATH`.
+    Sqlite,
+    /// Stores everything in the JSON file at `KV_FILE_PATH`.
+    File,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "redis" => Ok(Self::Redis),
+            "rest" | "upstash" => Ok(Self::Rest),
+            "memory" => Ok(Self::Memory),
+            "sqlite" => Ok(Self::Sqlite),
+            "file" => Ok(Self::File),
+            _ => Err(Error::InvalidBackend(s.to_string())),
+        }
+    }
+}
+
+/// A KV backend which is selected at runtime, e.g. via configuration.
+pub enum Store {
+    Redis(Redis),
+    Upstash(Upstash),
+    Memory(Memory),
+    Sqlite(Sqlite),
+    File(JsonFile),
+}
+
+impl Store {
+    /// Opens the given backend. `ensure_tls` only applies to Redis.
+    pub fn open(backend: Backend, ensure_tls: bool) -> Result<Self> {
+        Ok(match backend {
+            Backend::Redis => Redis::from_env(ensure_tls)?.into(),
+            Backend::Rest => Upstash::from_env()?.into(),
+            Backend::Memory => Memory::new().into(),
+            Backend::Sqlite => Sqlite::from_env()?.into(),
+            Backend::File => JsonFile::from_env()?.into(),
+        })
+    }
+
+    /// Opens the backend set in `KV_BACKEND`. When it is not set, the REST
+    /// API is used if `KV_REST_API_URL` is set, like it is on Vercel, and
+    /// Redis otherwise.
+    pub fn from_env(ensure_tls: bool) -> Result<Self> {
+        let backend = match env::var("KV_BACKEND") {
+            Ok(v) => v.parse()?,
+            Err(_) if env::var_os("KV_REST_API_URL").is_some() => Backend::Rest,
+            Err(_) => Backend::Redis,
+        };
+        Store::open(backend, ensure_tls)
+    }
+}
+
+/// Forwards a call to the selected backend.
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self {
+            Store::Redis(db) => db.$method($($arg),*).await,
+            Store::Upstash(db) => db.$method($($arg),*).await,
+            Store::Memory(db) => db.$method($($arg),*).await,
+            Store::Sqlite(db) => db.$method($($arg),*).await,
+            Store::File(db) => db.$method($($arg),*).await,
+        }
+    };
+}
+
+impl KV for Store {
+    async fn set(&self, key: impl AsRef<str> + Send, val: impl AsRef<str> + Send) -> Result<()> {
+        dispatch!(self.set(key, val))
+    }
+
+    async fn get(&self, key: impl AsRef<str> + Send) -> Result<Option<String>> {
+        dispatch!(self.get(key))
+    }
+
+    async fn delete(&self, key: impl AsRef<str> + Send) -> Result<()> {
+        dispatch!(self.delete(key))
+    }
+
+    async fn list_keys(&self, prefix: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        dispatch!(self.list_keys(prefix))
+    }
+
+    async fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> Result<()> {
+        dispatch!(self.set_with_ttl(key, val, ttl))
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        dispatch!(self.compare_and_swap(key, old, new))
+    }
+}
+
+impl From<Redis> for Store {
+    fn from(db: Redis) -> Self {
+        Store::Redis(db)
+    }
+}
+
+impl From<Upstash> for Store {
+    fn from(db: Upstash) -> Self {
+        Store::Upstash(db)
+    }
+}
+
+impl From<Memory> for Store {
+    fn from(db: Memory) -> Self {
+        Store::Memory(db)
+    }
+}
+
+impl From<Sqlite> for Store {
+    fn from(db: Sqlite) -> Self {
+        Store::Sqlite(db)
+    }
+}
+
+impl From<JsonFile> for Store {
+    fn from(db: JsonFile) -> Self {
+        Store::File(db)
+    }
+}
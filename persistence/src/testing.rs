@@ -0,0 +1,142 @@
+use crate::redis::COMPARE_AND_SWAP;
+use crate::KV;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Checks the behavior shared by all backends on an empty store.
+pub async fn test_operations(db: &impl KV) {
+    db.set("list:b", "1").await.unwrap();
+    db.set("list:a", "2").await.unwrap();
+    db.set("list*", "3").await.unwrap();
+    db.set("other", "4").await.unwrap();
+    assert_eq!(db.list_keys("list:").await.unwrap(), ["list:a", "list:b"]);
+    assert_eq!(db.list_keys("list*").await.unwrap(), ["list*"]);
+
+    db.delete("list:a").await.unwrap();
+    db.delete("missing").await.unwrap();
+    assert_eq!(db.get("list:a").await.unwrap(), None);
+    assert_eq!(db.list_keys("list:").await.unwrap(), ["list:b"]);
+
+    assert!(!db.compare_and_swap("cas", Some("1"), "2").await.unwrap());
+    assert!(db.compare_and_swap("cas", None, "1").await.unwrap());
+    assert!(!db.compare_and_swap("cas", None, "2").await.unwrap());
+    assert!(db.compare_and_swap("cas", Some("1"), "2").await.unwrap());
+    assert_eq!(db.get("cas").await.unwrap(), Some("2".into()));
+
+    // The margins are wide, so slow machines don't make the checks flaky.
+    db.set_with_ttl("ttl:short", "1", Duration::from_secs(1))
+        .await
+        .unwrap();
+    db.set_with_ttl("ttl:long", "2", Duration::from_secs(3600))
+        .await
+        .unwrap();
+    assert_eq!(db.get("ttl:long").await.unwrap(), Some("2".into()));
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(db.get("ttl:short").await.unwrap(), None);
+    assert_eq!(db.list_keys("ttl:").await.unwrap(), ["ttl:long"]);
+    assert!(db.compare_and_swap("ttl:short", None, "3").await.unwrap());
+}
+
+/// A reply to a Redis command.
+pub enum Reply {
+    Ok,
+    Nil,
+    Int(i64),
+    Bulk(String),
+    Array(Vec<Reply>),
+    Error(String),
+}
+
+impl Reply {
+    pub fn to_resp(&self) -> String {
+        match self {
+            Reply::Ok => "+OK\r\n".into(),
+            Reply::Nil => "$-1\r\n".into(),
+            Reply::Int(n) => format!(":{n}\r\n"),
+            Reply::Bulk(s) => format!("${}\r\n{s}\r\n", s.len()),
+            Reply::Array(items) => {
+                let resp: String = items.iter().map(Reply::to_resp).collect();
+                format!("*{}\r\n{resp}", items.len())
+            }
+            Reply::Error(err) => format!("-{err}\r\n"),
+        }
+    }
+
+    /// Returns the value in the format of the Upstash REST API.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Reply::Ok => json!("OK"),
+            Reply::Nil => Value::Null,
+            Reply::Int(n) => json!(n),
+            Reply::Bulk(s) => json!(s),
+            Reply::Array(items) => Value::Array(items.iter().map(Reply::to_json).collect()),
+            Reply::Error(_) => unreachable!("errors are not values"),
+        }
+    }
+}
+
+/// Executes the Redis commands sent by the Redis and Upstash backends, for
+/// stand-in servers.
+#[derive(Default)]
+pub struct Commands {
+    values: HashMap<String, (String, Option<Instant>)>,
+}
+
+impl Commands {
+    pub fn execute(&mut self, args: &[&str]) -> Reply {
+        let now = Instant::now();
+        self.values
+            .retain(|_, (_, expires_at)| expires_at.is_none_or(|t| t > now));
+
+        match *args {
+            ["SET", key, val] => self.set(key, val, None),
+            ["SET", key, val, "PX", ms] => {
+                let expires_at = now + Duration::from_millis(ms.parse().unwrap());
+                self.set(key, val, Some(expires_at))
+            }
+            ["GET", key] => match self.values.get(key) {
+                Some((val, _)) => Reply::Bulk(val.clone()),
+                None => Reply::Nil,
+            },
+            ["DEL", key] => Reply::Int(self.values.remove(key).is_some() as i64),
+            ["SCAN", _, "MATCH", pattern, "COUNT", _] => {
+                let prefix = unescape(pattern.strip_suffix('*').unwrap());
+                let keys = self
+                    .values
+                    .keys()
+                    .filter(|key| key.starts_with(&prefix))
+                    .map(|key| Reply::Bulk(key.clone()))
+                    .collect();
+                Reply::Array(vec![Reply::Bulk("0".into()), Reply::Array(keys)])
+            }
+            ["EVAL", COMPARE_AND_SWAP, "1", key, exists, old, new] => {
+                let current = self.values.get(key).map(|(val, _)| *val == old);
+                if current != (exists == "1").then_some(true) {
+                    return Reply::Int(0);
+                }
+                self.set(key, new, None);
+                Reply::Int(1)
+            }
+            _ => Reply::Error("ERR unknown command".into()),
+        }
+    }
+
+    fn set(&mut self, key: &str, val: &str, expires_at: Option<Instant>) -> Reply {
+        self.values
+            .insert(key.to_string(), (val.to_string(), expires_at));
+        Reply::Ok
+    }
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut chars = pattern.chars();
+    let mut s = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => s.extend(chars.next()),
+            c => s.push(c),
+        }
+    }
+    s
+}
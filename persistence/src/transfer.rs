@@ -0,0 +1,142 @@
+//! Moving the stored state between backends via a portable JSON dump.
+
+use crate::errors::{Error, Result};
+use crate::KV;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Version of the dumps written by [`export`].
+pub const DUMP_VERSION: u32 = 1;
+
+/// The keys and values of a store. Keys are relative to the namespace they
+/// have been exported from, so they can be imported into another one.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dump {
+    pub version: u32,
+    pub entries: BTreeMap<String, String>,
+}
+
+/// What importing a key does to the target store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Create,
+    Overwrite,
+    Unchanged,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Change::Create => "create",
+            Change::Overwrite => "overwrite",
+            Change::Unchanged => "unchanged",
+        })
+    }
+}
+
+/// Reads all keys in `namespace` starting with `prefix`. `namespace` is
+/// prepended to the keys as it is, e.g. `staging:`.
+pub async fn export(db: &impl KV, namespace: &str, prefix: &str) -> Result<Dump> {
+    let mut entries = BTreeMap::new();
+    for key in db.list_keys(format!("{namespace}{prefix}")).await? {
+        // The key might have been removed since listing it.
+        if let Some(val) = db.get(&key).await? {
+            entries.insert(key[namespace.len()..].to_string(), val);
+        }
+    }
+
+    Ok(Dump {
+        version: DUMP_VERSION,
+        entries,
+    })
+}
+
+/// Returns what importing the dump into `namespace` would change, without
+/// writing anything.
+pub async fn plan_import(
+    db: &impl KV,
+    namespace: &str,
+    dump: &Dump,
+) -> Result<Vec<(String, Change)>> {
+    if dump.version > DUMP_VERSION {
+        return Err(Error::UnsupportedDump(dump.version));
+    }
+
+    let mut changes = Vec::with_capacity(dump.entries.len());
+    for (key, val) in &dump.entries {
+        let change = match db.get(format!("{namespace}{key}")).await? {
+            None => Change::Create,
+            Some(current) if current == *val => Change::Unchanged,
+            Some(_) => Change::Overwrite,
+        };
+        changes.push((key.clone(), change));
+    }
+    Ok(changes)
+}
+
+/// Writes the dump into `namespace` and returns what has been changed.
+pub async fn import(db: &impl KV, namespace: &str, dump: &Dump) -> Result<Vec<(String, Change)>> {
+    let changes = plan_import(db, namespace, dump).await?;
+    for (key, change) in &changes {
+        if *change != Change::Unchanged {
+            db.set(format!("{namespace}{key}"), &dump.entries[key])
+                .await?;
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::Memory;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let source = Memory::from(HashMap::from([
+            ("prod:app_a".to_string(), "1".to_string()),
+            ("prod:app_b".to_string(), "2".to_string()),
+            ("prod:other".to_string(), "3".to_string()),
+            ("app_c".to_string(), "4".to_string()),
+        ]));
+        let dump = export(&source, "prod:", "app_").await.unwrap();
+        assert_eq!(
+            dump.entries,
+            BTreeMap::from([
+                ("app_a".to_string(), "1".to_string()),
+                ("app_b".to_string(), "2".to_string())
+            ])
+        );
+
+        let json = serde_json::to_string(&dump).unwrap();
+        let dump: Dump = serde_json::from_str(&json).unwrap();
+
+        let target = Memory::from(HashMap::from([("app_a".to_string(), "0".to_string())]));
+        let expected = vec![
+            ("app_a".to_string(), Change::Overwrite),
+            ("app_b".to_string(), Change::Create),
+        ];
+        assert_eq!(plan_import(&target, "", &dump).await.unwrap(), expected);
+        assert_eq!(target.dump().len(), 1);
+
+        assert_eq!(import(&target, "", &dump).await.unwrap(), expected);
+        assert_eq!(target.get("app_a").await.unwrap(), Some("1".into()));
+        assert_eq!(target.get("app_b").await.unwrap(), Some("2".into()));
+        assert!(import(&target, "", &dump)
+            .await
+            .unwrap()
+            .iter()
+            .all(|(_, change)| *change == Change::Unchanged));
+
+        let future = Dump {
+            version: DUMP_VERSION + 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            import(&target, "", &future).await,
+            Err(Error::UnsupportedDump(_))
+        ));
+    }
+}
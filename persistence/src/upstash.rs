@@ -0,0 +1,218 @@
+use super::errors::{Error, Result};
+use crate::redis::{prefix_pattern, COMPARE_AND_SWAP};
+use crate::KV;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Talks to the Upstash Redis REST API, which also backs Vercel KV, so no
+/// Redis connection has to be opened per request.
+pub struct Upstash {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl Upstash {
+    pub fn new(url: &str, token: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        Ok(Upstash {
+            client,
+            url: url.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let url = from_env!("KV_REST_API_URL")?;
+        let token = from_env!("KV_REST_API_TOKEN")?;
+        Upstash::new(&url, &token)
+    }
+
+    /// Sends a single Redis command and returns its result.
+    async fn command(&self, args: &[&str]) -> Result<Value> {
+        let res = self
+            .client
+            .post(&self.url)
+            .bearer_auth(&self.token)
+            .body(serde_json::to_string(args)?)
+            .send()
+            .await?;
+        let status = res.status().as_u16();
+        let body = res.text().await?;
+
+        let mut body: Value = serde_json::from_str(&body)
+            .map_err(|_| Error::Upstash(format!("unexpected response ({status}): {body}")))?;
+
+        if let Some(err) = body.get("error").and_then(Value::as_str) {
+            return Err(Error::Upstash(err.to_string()));
+        }
+
+        Ok(body["result"].take())
+    }
+}
+
+impl KV for Upstash {
+    async fn set(&self, key: impl AsRef<str> + Send, val: impl AsRef<str> + Send) -> Result<()> {
+        self.command(&["SET", key.as_ref(), val.as_ref()]).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: impl AsRef<str> + Send) -> Result<Option<String>> {
+        match self.command(&["GET", key.as_ref()]).await? {
+            Value::Null => Ok(None),
+            Value::String(v) => Ok(Some(v)),
+            v => Err(Error::Upstash(format!("unexpected result: {v}"))),
+        }
+    }
+
+    async fn delete(&self, key: impl AsRef<str> + Send) -> Result<()> {
+        self.command(&["DEL", key.as_ref()]).await?;
+        Ok(())
+    }
+
+    async fn list_keys(&self, prefix: impl AsRef<str> + Send) -> Result<Vec<String>> {
+        let pattern = prefix_pattern(prefix.as_ref());
+        let mut keys = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let res = self
+                .command(&["SCAN", &cursor, "MATCH", &pattern, "COUNT", "100"])
+                .await?;
+            let Some((next, page)) = res
+                .as_array()
+                .and_then(|res| Some((res.first()?.as_str()?, res.get(1)?.as_array()?)))
+            else {
+                return Err(Error::Upstash(format!("unexpected result: {res}")));
+            };
+
+            keys.extend(page.iter().filter_map(Value::as_str).map(str::to_string));
+            if next == "0" {
+                break;
+            }
+            cursor = next.to_string();
+        }
+
+        // SCAN may return a key more than once.
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    async fn set_with_ttl(
+        &self,
+        key: impl AsRef<str> + Send,
+        val: impl AsRef<str> + Send,
+        ttl: Duration,
+    ) -> Result<()> {
+        let ms = ttl.as_millis().max(1).to_string();
+        self.command(&["SET", key.as_ref(), val.as_ref(), "PX", &ms])
+            .await?;
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: impl AsRef<str> + Send,
+        old: Option<&str>,
+        new: impl AsRef<str> + Send,
+    ) -> Result<bool> {
+        let exists = if old.is_some() { "1" } else { "0" };
+        let args = [
+            "EVAL",
+            COMPARE_AND_SWAP,
+            "1",
+            key.as_ref(),
+            exists,
+            old.unwrap_or_default(),
+            new.as_ref(),
+        ];
+        match self.command(&args).await?.as_i64() {
+            Some(swapped) => Ok(swapped == 1),
+            None => Err(Error::Upstash("unexpected result".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{Commands, Reply};
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TOKEN: &str = "token";
+
+    /// Serves the Upstash REST API on a local port.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let mut commands = Commands::default();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&mut stream);
+
+                let mut authorized = false;
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.strip_prefix("content-length: ") {
+                        content_length = v.parse().unwrap();
+                    }
+                    authorized |= line == format!("authorization: bearer {TOKEN}");
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let command: Vec<String> = serde_json::from_slice(&body).unwrap();
+                let command: Vec<_> = command.iter().map(String::as_str).collect();
+                let (status, res) = match commands.execute(&command) {
+                    _ if !authorized => ("401 Unauthorized", json!({ "error": "Unauthorized" })),
+                    Reply::Error(err) => ("400 Bad Request", json!({ "error": err })),
+                    reply => ("200 OK", json!({ "result": reply.to_json() })),
+                };
+                let res = res.to_string();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{res}",
+                    res.len()
+                )
+                .unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn test_upstash() {
+        let url = serve();
+
+        let db = Upstash::new(&url, TOKEN).unwrap();
+        assert_eq!(db.get("a").await.unwrap(), None);
+        db.set("a", "1").await.unwrap();
+        assert_eq!(db.get("a").await.unwrap(), Some("1".into()));
+
+        let db = Upstash::new(&url, "invalid").unwrap();
+        assert!(matches!(db.get("a").await, Err(Error::Upstash(err)) if err == "Unauthorized"));
+    }
+
+    #[tokio::test]
+    async fn test_upstash_operations() {
+        let db = Upstash::new(&serve(), TOKEN).unwrap();
+        crate::testing::test_operations(&db).await;
+    }
+}
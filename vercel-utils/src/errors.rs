@@ -0,0 +1,27 @@
+use controller::errors::Error as ControllerError;
+use vercel_runtime::{http, Body, Error, Response};
+
+/// Responds to a failed `authorize_from_db`, the same way in all automations.
+pub fn authorization_error(err: ControllerError) -> Result<Response<Body>, Error> {
+    match err {
+        ControllerError::NoAuthToken => http::bad_request("no authorization token stored"),
+        ControllerError::TokenRevoked => http::unauthorized(
+            "spotify authorization has been revoked, authorize the app again at /api/oauth/login",
+        ),
+        err => http::internal_server_error(err.to_string()),
+    }
+}
+
+/// Responds to a failed playlist update, matching the status codes of the
+/// native server.
+pub fn update_error(err: ControllerError) -> Result<Response<Body>, Error> {
+    match err {
+        ControllerError::InvalidTimeRange | ControllerError::InvalidYear(_) => {
+            http::bad_request(err.to_string())
+        }
+        ControllerError::NoPlaylistFound | ControllerError::PlaylistDoesNotExist => {
+            http::not_found(err.to_string())
+        }
+        err => http::internal_server_error(err.to_string()),
+    }
+}
@@ -1,4 +1,6 @@
+mod errors;
 mod macros;
 mod urls;
 
+pub use errors::*;
 pub use urls::*;